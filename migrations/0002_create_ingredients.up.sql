@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS ingredients (
+  recipe_id VARCHAR(200) NOT NULL,
+  position INTEGER NOT NULL,
+  raw TEXT NOT NULL,
+  quantity REAL,
+  quantity_max REAL,
+  unit VARCHAR(50),
+  item TEXT NOT NULL,
+  note TEXT,
+  PRIMARY KEY (recipe_id, position),
+  FOREIGN KEY (recipe_id) REFERENCES recipes(id)
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE recipes ADD COLUMN servings INTEGER;
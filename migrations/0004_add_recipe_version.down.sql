@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE recipes DROP COLUMN updated_at;
+ALTER TABLE recipes DROP COLUMN version;
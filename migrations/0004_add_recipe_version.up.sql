@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE recipes ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE recipes ADD COLUMN updated_at TEXT;
+UPDATE recipes SET updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now');
@@ -0,0 +1,27 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS recipe_revisions (
+  recipe_id VARCHAR(200) NOT NULL,
+  version INTEGER NOT NULL,
+  snapshot TEXT NOT NULL,
+  author VARCHAR(200) NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (recipe_id, version)
+);
+
+-- Existing recipes start their history at their current version.
+INSERT INTO recipe_revisions (recipe_id, version, snapshot, author, created_at)
+SELECT
+  id,
+  version,
+  json_object(
+    'id', id,
+    'title', title,
+    'ingredients', ingredients,
+    'instructions', instructions,
+    'source', recipe_source,
+    'servings', servings,
+    'tags', (SELECT json_group_array(tag) FROM tags WHERE recipe_id = recipes.id)
+  ),
+  'unknown',
+  COALESCE(updated_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+FROM recipes;
@@ -0,0 +1,26 @@
+-- Add up migration script here
+CREATE VIRTUAL TABLE IF NOT EXISTS recipes_fts USING fts5(
+  recipe_id UNINDEXED,
+  title,
+  ingredients,
+  instructions,
+  tokenize = 'porter unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS recipes_fts_insert AFTER INSERT ON recipes BEGIN
+  INSERT INTO recipes_fts (recipe_id, title, ingredients, instructions)
+  VALUES (new.id, new.title, new.ingredients, new.instructions);
+END;
+
+CREATE TRIGGER IF NOT EXISTS recipes_fts_update AFTER UPDATE ON recipes BEGIN
+  DELETE FROM recipes_fts WHERE recipe_id = old.id;
+  INSERT INTO recipes_fts (recipe_id, title, ingredients, instructions)
+  VALUES (new.id, new.title, new.ingredients, new.instructions);
+END;
+
+CREATE TRIGGER IF NOT EXISTS recipes_fts_delete AFTER DELETE ON recipes BEGIN
+  DELETE FROM recipes_fts WHERE recipe_id = old.id;
+END;
+
+INSERT INTO recipes_fts (recipe_id, title, ingredients, instructions)
+SELECT id, title, ingredients, instructions FROM recipes;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX recipes_created_at;
+DROP INDEX recipes_title;
+ALTER TABLE recipes DROP COLUMN created_at;
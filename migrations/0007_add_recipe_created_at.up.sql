@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE recipes ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+UPDATE recipes SET created_at = COALESCE(
+    (SELECT min(created_at) FROM recipe_revisions WHERE recipe_id = recipes.id),
+    updated_at,
+    strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+);
+CREATE INDEX recipes_title ON recipes (title COLLATE NOCASE, id);
+CREATE INDEX recipes_created_at ON recipes (created_at, id);
@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS users (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  email VARCHAR(320) NOT NULL UNIQUE,
+  display_name VARCHAR(200) NOT NULL,
+  -- NULL for accounts made through the access code, which cannot log in.
+  password_hash TEXT,
+  created_at TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE users ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'contributor'
+  CHECK (role IN ('viewer', 'contributor', 'editor', 'admin'));
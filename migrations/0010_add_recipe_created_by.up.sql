@@ -0,0 +1,11 @@
+-- Add up migration script here
+ALTER TABLE recipes ADD COLUMN created_by TEXT;
+
+-- Recipes first written by a user account belong to that user.
+UPDATE recipes SET created_by = (
+  SELECT r.author FROM recipe_revisions r
+  JOIN users u ON CAST(u.id AS TEXT) = r.author
+  WHERE r.recipe_id = recipes.id
+  ORDER BY r.version
+  LIMIT 1
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE revoked_tokens;
+DROP INDEX refresh_tokens_family;
+DROP TABLE refresh_tokens;
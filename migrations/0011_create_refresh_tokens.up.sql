@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  token_hash CHAR(64) PRIMARY KEY,
+  -- Every token rotated from the same login shares a family.
+  family CHAR(32) NOT NULL,
+  user_id INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  revoked_at TEXT,
+  FOREIGN KEY (user_id) REFERENCES users(id)
+);
+CREATE INDEX refresh_tokens_family ON refresh_tokens (family);
+
+-- Access tokens revoked before they expire, by `jti`.
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+  jti CHAR(32) PRIMARY KEY,
+  expires_at INTEGER NOT NULL
+);
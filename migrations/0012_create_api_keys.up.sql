@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS api_keys (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL,
+  name VARCHAR(200) NOT NULL,
+  prefix VARCHAR(20) NOT NULL,
+  key_hash CHAR(64) NOT NULL UNIQUE,
+  -- Space separated, e.g. 'recipes:read recipes:write'.
+  scopes TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT,
+  last_used_at TEXT,
+  revoked_at TEXT,
+  FOREIGN KEY (user_id) REFERENCES users(id)
+);
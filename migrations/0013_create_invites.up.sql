@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS invites (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  code_hash CHAR(64) NOT NULL UNIQUE,
+  note VARCHAR(200),
+  max_uses INTEGER NOT NULL CHECK (max_uses > 0),
+  uses INTEGER NOT NULL DEFAULT 0,
+  expires_at TEXT,
+  -- Id of the admin who minted it, or NULL when made from the command line.
+  created_by INTEGER,
+  created_at TEXT NOT NULL,
+  revoked_at TEXT,
+  FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+ALTER TABLE users ADD COLUMN invite_id INTEGER REFERENCES invites(id);
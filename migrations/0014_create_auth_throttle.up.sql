@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS auth_throttle (
+  -- 'ip' or 'email'.
+  kind VARCHAR(10) NOT NULL,
+  identity VARCHAR(200) NOT NULL,
+  failures INTEGER NOT NULL,
+  -- Unix timestamps.
+  last_failure_at INTEGER NOT NULL,
+  locked_until INTEGER,
+  PRIMARY KEY (kind, identity)
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TRIGGER audit_log_no_delete;
+DROP TRIGGER audit_log_no_update;
+DROP TABLE audit_log;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS audit_log (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  at TEXT NOT NULL,
+  -- User id from the token, or NULL for anonymous requests.
+  actor VARCHAR(40),
+  action VARCHAR(40) NOT NULL,
+  target VARCHAR(200),
+  request_id VARCHAR(64),
+  before_json TEXT,
+  after_json TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_at ON audit_log (at);
+CREATE INDEX IF NOT EXISTS audit_log_actor ON audit_log (actor, id);
+CREATE INDEX IF NOT EXISTS audit_log_action ON audit_log (action, id);
+
+-- Append only: entries can be added but never changed or removed.
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+  SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+  SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Request id the client sent, kept apart from the one the server generated.
+ALTER TABLE audit_log ADD COLUMN client_request_id VARCHAR(64);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Email an invite is reserved for. Such an invite is single-use and may also
+-- set the password of that email's passwordless account.
+ALTER TABLE invites ADD COLUMN email VARCHAR(200);
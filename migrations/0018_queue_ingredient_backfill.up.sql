@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Recipes whose ingredient lists have not been parsed into rows. Parsing needs
+-- the server's ingredient parser, so the server works through this queue once
+-- and leaves it empty; recipes written since always store their rows.
+CREATE TABLE IF NOT EXISTS ingredient_backfill (
+  recipe_id VARCHAR(200) PRIMARY KEY
+);
+
+INSERT INTO ingredient_backfill (recipe_id)
+SELECT id FROM recipes
+WHERE NOT EXISTS (SELECT 1 FROM ingredients WHERE recipe_id = recipes.id);
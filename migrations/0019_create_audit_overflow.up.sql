@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- Rejected requests from one client IP beyond the audit log's per-minute cap,
+-- counted per minute until they are written to the log as one summary entry.
+CREATE TABLE IF NOT EXISTS audit_overflow (
+  action VARCHAR(40) NOT NULL,
+  ip VARCHAR(45),
+  minute TEXT NOT NULL,
+  suppressed INTEGER NOT NULL,
+  PRIMARY KEY (action, ip, minute)
+);
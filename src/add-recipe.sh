@@ -2,28 +2,34 @@
 
 API_URL="http://localhost:3000/api/v1"
 
-# Get user info
-read -p "Full name: " full_name
-read -p "Email: " email
-read -p "Access code: " access_code
-echo
+if [[ -n "$RECIPE_API_KEY" ]]; then
+    # API keys from /api-keys work as Bearer tokens and do not expire daily
+    token="$RECIPE_API_KEY"
+else
+    # Get user info
+    read -p "Full name: " full_name
+    read -p "Email: " email
+    read -p "Invite code: " access_code
+    echo
 
-echo "Registering..."
-response=$(curl -s -X POST "$API_URL/register" \
-    -H "Content-Type: application/json" \
-    -d "{\"full_name\": \"$full_name\", \"email\": \"$email\", \"access_code\": \"$access_code\"}"
-    )
+    echo "Registering..."
+    response=$(curl -s -X POST "$API_URL/register" \
+        -H "Content-Type: application/json" \
+        -d "{\"full_name\": \"$full_name\", \"email\": \"$email\", \"access_code\": \"$access_code\"}"
+        )
 
-token=$(echo "$response" | jq -r '.access_token')
+    token=$(echo "$response" | jq -r '.access_token')
 
-if [[ "$token" == "null" ]]; then
-    echo
-    echo "Registration failed:"
-    echo "$response"
-    exit 1
+    if [[ "$token" == "null" ]]; then
+        echo
+        echo "Registration failed:"
+        echo "$response"
+        exit 1
+    fi
+
+    echo "Registration complete"
 fi
 
-echo "Registration complete"
 echo "Adding recipe..."
 
 recipe='{
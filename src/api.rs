@@ -10,10 +10,30 @@ pub struct ApiDoc;
 
 pub fn router() -> OpenApiRouter<Arc<RwLock<AppState>>> {
     OpenApiRouter::new()
-        .routes(routes!(get_recipe_by_id))
+        .routes(routes!(get_recipe_by_id, update_recipe, patch_recipe, delete_recipe))
+        .routes(routes!(list_revisions))
+        .routes(routes!(get_revision))
+        .routes(routes!(diff_revisions))
+        .routes(routes!(restore_revision))
+        .routes(routes!(list_recipes))
         .routes(routes!(get_random_recipe))
+        .routes(routes!(search_recipes))
         .routes(routes!(get_recipe_by_tag))
         .routes(routes!(register))
+        .routes(routes!(signup, list_users))
+        .routes(routes!(set_user_role))
+        .routes(routes!(login))
+        .routes(routes!(refresh_token))
+        .routes(routes!(logout))
+        .routes(routes!(create_api_key, list_api_keys))
+        .routes(routes!(revoke_api_key))
+        .routes(routes!(create_invite, list_invites))
+        .routes(routes!(revoke_invite))
+        .routes(routes!(list_lockouts))
+        .routes(routes!(clear_lockout))
+        .routes(routes!(get_audit_log))
+        .routes(routes!(export_recipes))
+        .routes(routes!(import_recipes))
         .routes(routes!(add_recipe))
 }
 
@@ -30,39 +50,412 @@ pub fn router() -> OpenApiRouter<Arc<RwLock<AppState>>> {
         (status = 201, description = "Recipe was added", body = ()),
         (status = 400, description = "Bad request", body = String),
         (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low", body = authjwt::AuthError),
     )
 )]
 pub async fn add_recipe(
-    _claims: authjwt::Claims,
+    Require(claims, _): Require<roles::Contributor>,
     State(app_state): State<SharedAppState>,
+    request_id: audit::RequestId,
     Json(recipe): Json<JSONRecipe>,
 ) -> axum::response::Response {
     let app_state = app_state.read().await;
-    match recipe::add(&app_state.db, recipe).await {
+    let recipe_id = recipe.id.clone();
+    match recipe::add(&app_state.db, recipe, &claims.sub).await {
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
-        Ok(()) => StatusCode::CREATED.into_response(),
+        Ok(()) => {
+            audit_recipe(&app_state.db, &request_id, "recipe.add", &claims, &recipe_id, None).await;
+            StatusCode::CREATED.into_response()
+        }
+    }
+}
+
+
+/// Status for a failed recipe write: missing recipes are 404, anything else 400.
+fn write_error(e: sqlx::Error) -> axum::response::Response {
+    match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND.into_response(),
+        e => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Status for a failed conditional write: a row that vanished or moved on is 412.
+fn stale_write_error(e: sqlx::Error) -> axum::response::Response {
+    match e {
+        sqlx::Error::RowNotFound => StatusCode::PRECONDITION_FAILED.into_response(),
+        e => write_error(e),
+    }
+}
+
+/// Strong ETag for a recipe version.
+fn etag(version: i64) -> ETag {
+    format!("\"{}\"", version)
+        .parse()
+        .expect("version is a valid etag")
+}
+
+/// Media ranges of the `Accept` header, lowercased, with their `q` weights.
+fn accept_ranges(headers: &http::HeaderMap) -> Vec<(String, f32)> {
+    headers
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let media_range = params.next()?.trim().to_ascii_lowercase();
+            if media_range.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some((media_range, q))
+        })
+        .collect()
+}
+
+/// Weight the ranges give `media_type`, taken from the most specific range that
+/// matches it, along with that specificity: 2 for the type itself, 1 for
+/// `type/*` and 0 for `*/*`.
+fn weight(ranges: &[(String, f32)], media_type: &str) -> Option<(f32, u8)> {
+    let (kind, _) = media_type.split_once('/')?;
+    ranges
+        .iter()
+        .filter_map(|(range, q)| {
+            let specificity = match range.split_once('/')? {
+                _ if range == media_type => 2,
+                (range_kind, "*") if range_kind == kind => 1,
+                ("*", "*") => 0,
+                _ => return None,
+            };
+            Some((*q, specificity))
+        })
+        .max_by_key(|&(_, specificity)| specificity)
+}
+
+/// Form `get_recipe_by_id` returns a recipe in, picked from the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Representation {
+    Json,
+    JsonLd,
+    Cooklang,
+}
+
+impl Representation {
+    /// Every form with its media type, the first preferred when weights tie.
+    const OFFERED: [(Representation, &'static str); 3] = [
+        (Representation::Json, "application/json"),
+        (Representation::JsonLd, "application/ld+json"),
+        (Representation::Cooklang, "text/x-cooklang"),
+    ];
+
+    /// The offered form the `Accept` header weights highest, preferring one it
+    /// names over one it only matches with a wildcard. Plain JSON when it names
+    /// none of them or refuses them all.
+    fn from_headers(headers: &http::HeaderMap) -> Self {
+        let ranges = accept_ranges(headers);
+        let mut best = (Representation::Json, 0.0, 0);
+        for (representation, media_type) in Self::OFFERED {
+            if let Some((q, specificity)) = weight(&ranges, media_type)
+                && (q > best.1 || (q == best.1 && q > 0.0 && specificity > best.2))
+            {
+                best = (representation, q, specificity);
+            }
+        }
+        best.0
+    }
+
+    /// ETag for this form of a recipe version, so caches never answer a request
+    /// for one form with another. Plain JSON keeps the bare version that
+    /// `If-Match` takes.
+    fn etag(self, version: i64) -> ETag {
+        let suffix = match self {
+            Representation::Json => return etag(version),
+            Representation::JsonLd => "ld",
+            Representation::Cooklang => "cook",
+        };
+        format!("\"{}-{}\"", version, suffix)
+            .parse()
+            .expect("version is a valid etag")
+    }
+}
+
+/// Check the caller may write the recipe and `If-Match` matches its current version.
+///
+/// Only the recipe's owner or an editor may change it; anyone else gets 403.
+/// Writes without `If-Match` get 428 so clients cannot clobber edits they have
+/// not seen, and a stale tag gets 412. Returns the version the write must match.
+async fn precondition(
+    db: &SqlitePool,
+    recipe_id: &str,
+    claims: &authjwt::Claims,
+    headers: &http::HeaderMap,
+) -> Result<i64, axum::response::Response> {
+    use axum_extra::headers::HeaderMapExt;
+
+    let owner = recipe::owner(db, recipe_id).await.map_err(write_error)?;
+    if claims.role < Role::Editor && owner.as_deref() != Some(claims.sub.as_str()) {
+        return Err(authjwt::AuthError::NotOwner.into_response());
+    }
+    let version = recipe::version(db, recipe_id).await.map_err(write_error)?;
+    if !headers.contains_key(http::header::IF_MATCH) {
+        return Err((StatusCode::PRECONDITION_REQUIRED, "If-Match header required").into_response());
+    }
+    match headers.typed_get::<IfMatch>() {
+        Some(if_match) if if_match.precondition_passes(&etag(version)) => Ok(version),
+        _ => Err(StatusCode::PRECONDITION_FAILED.into_response()),
+    }
+}
+
+/// A recipe as JSON for the audit log, if it exists.
+async fn recipe_snapshot(db: &SqlitePool, recipe_id: &str) -> Option<serde_json::Value> {
+    let (recipe, tags) = recipe::get(db, recipe_id).await.ok()?;
+    let ingredients = ingredient::load(db, recipe_id).await.ok()?;
+    Some(serde_json::json!(JSONRecipe::new(recipe, tags, ingredients)))
+}
+
+/// Record a recipe write in the audit log, with the recipe as it is now.
+async fn audit_recipe(
+    db: &SqlitePool,
+    request_id: &audit::RequestId,
+    action: &'static str,
+    claims: &authjwt::Claims,
+    recipe_id: &str,
+    before: Option<serde_json::Value>,
+) {
+    let mut event = audit::Event::new(action)
+        .actor(&claims.sub)
+        .target(recipe_id)
+        .before(before);
+    if let Some(after) = recipe_snapshot(db, recipe_id).await {
+        event = event.after(after);
+    }
+    audit::record(db, request_id, event).await;
+}
+
+/// Fetch a recipe after a write and return it as JSON with its new ETag.
+async fn updated_recipe(db: &SqlitePool, recipe_id: &str) -> axum::response::Response {
+    let stored = match recipe::get(db, recipe_id).await {
+        Ok(stored) => stored,
+        Err(e) => return write_error(e),
+    };
+    match ingredient::load(db, recipe_id).await {
+        Ok(ingredients) => {
+            let (recipe, tags) = stored;
+            let tag = TypedHeader(etag(recipe.version));
+            (tag, &JSONRecipe::new(recipe, tags, ingredients)).into_response()
+        }
+        Err(e) => write_error(e),
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/recipe/{recipe_id}",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        ("If-Match" = String, Header, description = "ETag of the recipe being replaced"),
+    ),
+    request_body(
+        content = inline(JSONRecipe),
+        description = "Replacement recipe; the ID in the path wins over the body"
+    ),
+    responses(
+        (status = 200, description = "Recipe was replaced", body = JSONRecipe),
+        (status = 400, description = "Bad request", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low or not the owner", body = authjwt::AuthError),
+        (status = 404, description = "No matching recipe"),
+        (status = 412, description = "Recipe changed since the ETag was read"),
+        (status = 428, description = "If-Match header missing", body = String),
+    )
+)]
+pub async fn update_recipe(
+    Require(claims, _): Require<roles::Contributor>,
+    State(app_state): State<SharedAppState>,
+    Path(recipe_id): Path<String>,
+    headers: http::HeaderMap,
+    request_id: audit::RequestId,
+    Json(recipe): Json<JSONRecipe>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let version = match precondition(&app_state.db, &recipe_id, &claims, &headers).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let before = recipe_snapshot(&app_state.db, &recipe_id).await;
+    match recipe::update(&app_state.db, &recipe_id, recipe, version, &claims.sub).await {
+        Err(e) => stale_write_error(e),
+        Ok(()) => {
+            audit_recipe(&app_state.db, &request_id, "recipe.update", &claims, &recipe_id, before).await;
+            updated_recipe(&app_state.db, &recipe_id).await
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/recipe/{recipe_id}",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        ("If-Match" = String, Header, description = "ETag of the recipe being updated"),
+    ),
+    request_body(
+        content = recipe::RecipePatch,
+        description = "Fields to change; tags can be replaced, added or removed"
+    ),
+    responses(
+        (status = 200, description = "Recipe was updated", body = JSONRecipe),
+        (status = 400, description = "Bad request", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low or not the owner", body = authjwt::AuthError),
+        (status = 404, description = "No matching recipe"),
+        (status = 412, description = "Recipe changed since the ETag was read"),
+        (status = 428, description = "If-Match header missing", body = String),
+    )
+)]
+pub async fn patch_recipe(
+    Require(claims, _): Require<roles::Contributor>,
+    State(app_state): State<SharedAppState>,
+    Path(recipe_id): Path<String>,
+    headers: http::HeaderMap,
+    request_id: audit::RequestId,
+    Json(patch): Json<recipe::RecipePatch>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let version = match precondition(&app_state.db, &recipe_id, &claims, &headers).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let before = recipe_snapshot(&app_state.db, &recipe_id).await;
+    match recipe::patch(&app_state.db, &recipe_id, patch, version, &claims.sub).await {
+        Err(e) => stale_write_error(e),
+        Ok(()) => {
+            audit_recipe(&app_state.db, &request_id, "recipe.patch", &claims, &recipe_id, before).await;
+            updated_recipe(&app_state.db, &recipe_id).await
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/recipe/{recipe_id}",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        ("If-Match" = String, Header, description = "ETag of the recipe being deleted"),
+    ),
+    responses(
+        (status = 204, description = "Recipe was deleted"),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low or not the owner", body = authjwt::AuthError),
+        (status = 404, description = "No matching recipe"),
+        (status = 412, description = "Recipe changed since the ETag was read"),
+        (status = 428, description = "If-Match header missing", body = String),
+    )
+)]
+pub async fn delete_recipe(
+    Require(claims, _): Require<roles::Contributor>,
+    State(app_state): State<SharedAppState>,
+    Path(recipe_id): Path<String>,
+    headers: http::HeaderMap,
+    request_id: audit::RequestId,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let version = match precondition(&app_state.db, &recipe_id, &claims, &headers).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let before = recipe_snapshot(&app_state.db, &recipe_id).await;
+    match recipe::delete(&app_state.db, &recipe_id, version).await {
+        Err(e) => stale_write_error(e),
+        Ok(()) => {
+            audit_recipe(&app_state.db, &request_id, "recipe.delete", &claims, &recipe_id, before).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+    }
+}
 
 #[utoipa::path(
     get,
     path = "/recipe/{recipe_id}",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        recipe::ScaleParams,
+        units::UnitsParams,
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy"),
+        ("Accept" = Option<String>, Header,
+            description = "`application/ld+json` for a schema.org Recipe, `text/x-cooklang` for Cooklang"),
+    ),
     responses(
-        (status = 200, description = "Get recipe by ID", body = [JSONRecipe]),
+        (status = 200, description = "Get recipe by ID", content(
+            (JSONRecipe = "application/json"),
+            (Object = "application/ld+json"),
+            (String = "text/x-cooklang"),
+        ), headers(("ETag" = String, description = "Current recipe version, suffixed `-ld` or `-cook` for those forms"))),
+        (status = 304, description = "Cached copy is still current"),
+        (status = 400, description = "Invalid scale or servings", body = String),
         (status = 404, description = "No matching recipe"),
     )
 )]
 pub async fn get_recipe_by_id(
     State(app_state): State<Arc<RwLock<AppState>>>,
     Path(recipe_id): Path<String>,
+    Query(scale): Query<recipe::ScaleParams>,
+    Query(units): Query<units::UnitsParams>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    headers: http::HeaderMap,
 ) -> Result<response::Response, http::StatusCode> {
     let app = app_state.write().await;
     let db = &app.db;
     let recipe_result = recipe::get(db, &recipe_id).await;
 
     match recipe_result {
-        Ok((recipe, tags)) => Ok(JSONRecipe::new(recipe, tags).into_response()),
+        Ok((mut recipe, tags)) => {
+            let representation = Representation::from_headers(&headers);
+            let tag = representation.etag(recipe.version);
+            let vary = [(http::header::VARY, "Accept")];
+            if let Some(TypedHeader(if_none_match)) = if_none_match
+                && !if_none_match.precondition_passes(&tag)
+            {
+                return Ok((StatusCode::NOT_MODIFIED, TypedHeader(tag), vary).into_response());
+            }
+            let mut ingredients = ingredient::load(db, &recipe_id).await.map_err(|e| {
+                log::error!("api:get_recipe_by_id ingredients failed: {}", e);
+                http::StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            match scale.factor(recipe.servings) {
+                Ok(Some(factor)) => {
+                    recipe.scale(factor);
+                    ingredients = ingredient::scale_all(&ingredients, factor);
+                }
+                Ok(None) => (),
+                Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+            }
+            if let Some(system) = units.units {
+                recipe.convert_units(system);
+                ingredients = units::convert_all(&ingredients, system);
+            }
+            match representation {
+                Representation::JsonLd => {
+                    let content_type = [(http::header::CONTENT_TYPE, "application/ld+json")];
+                    let document = Json(jsonld::document(&recipe, &tags));
+                    Ok((TypedHeader(tag), vary, content_type, document).into_response())
+                }
+                Representation::Cooklang => {
+                    let content_type = [(http::header::CONTENT_TYPE, "text/x-cooklang; charset=utf-8")];
+                    let document = cooklang::render(&recipe, &tags);
+                    Ok((TypedHeader(tag), vary, content_type, document).into_response())
+                }
+                Representation::Json => {
+                    let recipe = JSONRecipe::new(recipe, tags, ingredients);
+                    Ok((TypedHeader(tag), vary, &recipe).into_response())
+                }
+            }
+        }
         Err(e) => {
             log::warn!("api:get_recipe_by_id failed: {}", e);
             Err(http::StatusCode::NOT_FOUND)
@@ -70,9 +463,139 @@ pub async fn get_recipe_by_id(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/recipe/{recipe_id}/revisions",
+    params(("recipe_id" = String, Path, description = "Recipe ID")),
+    responses(
+        (status = 200, description = "Revision history, oldest first", body = [revision::RevisionSummary]),
+        (status = 404, description = "No revisions for this recipe"),
+    )
+)]
+pub async fn list_revisions(
+    State(app_state): State<SharedAppState>,
+    Path(recipe_id): Path<String>,
+) -> Result<response::Response, http::StatusCode> {
+    let app_state = app_state.read().await;
+    match revision::list(&app_state.db, &recipe_id).await {
+        Ok(revisions) if !revisions.is_empty() => Ok(Json(revisions).into_response()),
+        Ok(_) => Err(http::StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::warn!("api:list_revisions failed: {}", e);
+            Err(http::StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/recipe/{recipe_id}/revisions/{version}",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        ("version" = i64, Path, description = "Recipe version"),
+    ),
+    responses(
+        (status = 200, description = "Recipe snapshot at this version", body = revision::Revision),
+        (status = 404, description = "No matching revision"),
+    )
+)]
+pub async fn get_revision(
+    State(app_state): State<SharedAppState>,
+    Path((recipe_id, version)): Path<(String, i64)>,
+) -> Result<response::Response, http::StatusCode> {
+    let app_state = app_state.read().await;
+    match revision::get(&app_state.db, &recipe_id, version).await {
+        Ok(revision) => Ok(Json(revision).into_response()),
+        Err(e) => {
+            log::warn!("api:get_revision failed: {}", e);
+            Err(http::StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+/// Versions to compare in a revision diff.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+pub struct DiffParams {
+    /// Older version.
+    from: i64,
+    /// Newer version.
+    to: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/recipe/{recipe_id}/revisions/diff",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        DiffParams,
+    ),
+    responses(
+        (status = 200, description = "Line diff of ingredients and instructions", body = revision::RevisionDiff),
+        (status = 404, description = "No matching revisions"),
+    )
+)]
+pub async fn diff_revisions(
+    State(app_state): State<SharedAppState>,
+    Path(recipe_id): Path<String>,
+    Query(params): Query<DiffParams>,
+) -> Result<response::Response, http::StatusCode> {
+    let app_state = app_state.read().await;
+    match revision::diff(&app_state.db, &recipe_id, params.from, params.to).await {
+        Ok(diff) => Ok(Json(diff).into_response()),
+        Err(e) => {
+            log::warn!("api:diff_revisions failed: {}", e);
+            Err(http::StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/recipe/{recipe_id}/revisions/{version}/restore",
+    params(
+        ("recipe_id" = String, Path, description = "Recipe ID"),
+        ("version" = i64, Path, description = "Version to restore"),
+        ("If-Match" = String, Header, description = "ETag of the current recipe"),
+    ),
+    responses(
+        (status = 200, description = "Recipe restored as a new revision", body = JSONRecipe),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low or not the owner", body = authjwt::AuthError),
+        (status = 404, description = "No matching recipe or revision"),
+        (status = 412, description = "Recipe changed since the ETag was read"),
+        (status = 428, description = "If-Match header missing", body = String),
+    )
+)]
+pub async fn restore_revision(
+    Require(claims, _): Require<roles::Contributor>,
+    State(app_state): State<SharedAppState>,
+    Path((recipe_id, restore)): Path<(String, i64)>,
+    headers: http::HeaderMap,
+    request_id: audit::RequestId,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let version = match precondition(&app_state.db, &recipe_id, &claims, &headers).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let snapshot = match revision::get(&app_state.db, &recipe_id, restore).await {
+        Ok(revision) => revision.recipe,
+        Err(e) => return write_error(e),
+    };
+    let before = recipe_snapshot(&app_state.db, &recipe_id).await;
+    match recipe::update(&app_state.db, &recipe_id, snapshot, version, &claims.sub).await {
+        Err(e) => stale_write_error(e),
+        Ok(()) => {
+            audit_recipe(&app_state.db, &request_id, "recipe.restore", &claims, &recipe_id, before).await;
+            updated_recipe(&app_state.db, &recipe_id).await
+        }
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/recipe/random",
+    params(units::UnitsParams),
     responses(
         (status = 200, description = "Get random recipe", body = [JSONRecipe]),
         (status = 404, description = "No recipes available"),
@@ -80,13 +603,24 @@ pub async fn get_recipe_by_id(
 )]
 pub async fn get_random_recipe(
     State(app_state): State<Arc<RwLock<AppState>>>,
+    Query(units): Query<units::UnitsParams>,
 ) -> Result<response::Response, http::StatusCode> {
     let app = app_state.write().await;
     let db = &app.db;
     let recipe_result = recipe::get_random(db).await;
 
     match recipe_result {
-        Ok((recipe, tags)) => Ok(JSONRecipe::new(recipe, tags).into_response()),
+        Ok((mut recipe, tags)) => {
+            let mut ingredients = ingredient::load(db, &recipe.id).await.map_err(|e| {
+                log::error!("api:get_random_recipe ingredients failed: {}", e);
+                http::StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            if let Some(system) = units.units {
+                recipe.convert_units(system);
+                ingredients = units::convert_all(&ingredients, system);
+            }
+            Ok(JSONRecipe::new(recipe, tags, ingredients).into_response())
+        }
         Err(e) => {
             log::warn!("api:get_random_recipe failed: {}", e);
             Err(http::StatusCode::NOT_FOUND)
@@ -94,30 +628,192 @@ pub async fn get_random_recipe(
     }
 }
 
+/// Most recipes a tag lookup will return.
+const MAX_TAG_MATCHES: i64 = 50;
+
+/// How a list of `tag` parameters is combined.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+    /// Match recipes with at least one of the tags.
+    #[default]
+    Any,
+    /// Match recipes with every tag.
+    All,
+}
+
+/// Query parameters for looking recipes up by tag.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TagLookupParams {
+    /// Tag to match; repeat for several tags, up to 64.
+    #[serde(default)]
+    tag: Vec<String>,
+    /// Whether recipes need `any` or `all` of the tags.
+    mode: Option<TagMode>,
+    /// Tag expression such as `vegan AND (dessert OR snack) AND NOT nuts`, combined with
+    /// any `tag` parameters using AND.
+    query: Option<String>,
+    /// Return a list of up to this many random matches instead of a single recipe.
+    limit: Option<i64>,
+}
+
+impl TagLookupParams {
+    /// Combine the `tag` list and `query` expression into one tag query.
+    fn tag_query(self) -> Result<TagQuery, TagQueryError> {
+        if self.tag.len() > tagquery::MAX_TERMS {
+            return Err(TagQueryError::TooManyTerms(tagquery::MAX_TERMS));
+        }
+        let tags = match (self.tag.is_empty(), self.mode.unwrap_or_default()) {
+            (true, _) => None,
+            (false, TagMode::Any) => Some(TagQuery::any(self.tag)),
+            (false, TagMode::All) => Some(TagQuery::all(self.tag)),
+        };
+        let expression = self.query.as_deref().map(TagQuery::parse).transpose()?;
+        match (tags, expression) {
+            (Some(tags), Some(expression)) => Ok(TagQuery::And(vec![tags, expression])),
+            (Some(query), None) | (None, Some(query)) => Ok(query),
+            (None, None) => Err(TagQueryError::Empty),
+        }
+    }
+}
+
+/// One random tag match, or a list of matches when a `limit` is given.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum TagMatches {
+    One(Box<JSONRecipe>),
+    Many(Vec<JSONRecipe>),
+}
+
 #[utoipa::path(
     get,
     path = "/recipe/by-tags",
+    params(TagLookupParams, units::UnitsParams),
     responses(
-        (status = 200, description = "Get recipe that has at least one matching tag.", body = [JSONRecipe]),
+        (status = 200, description = "A random matching recipe, or a list of them with `limit`", body = TagMatches),
+        (status = 400, description = "No tags, too many tags or a malformed tag query", body = String),
         (status = 404, description = "No matching recipes"),
     )
 )]
 pub async fn get_recipe_by_tag(
     State(app_state): State<Arc<RwLock<AppState>>>,
-    Json(tags): Json<Vec<String>>,
+    axum_extra::extract::Query(params): axum_extra::extract::Query<TagLookupParams>,
+    Query(units): Query<units::UnitsParams>,
 ) -> Result<response::Response, http::StatusCode> {
-    log::info!("api:get_recipe_by_tag tags: {:?}", tags);
+    let limit = params.limit;
+    let query = match params.tag_query() {
+        Ok(query) => query,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+    log::info!("api:get_recipe_by_tag query: {:?}", query);
     let app_reader = app_state.read().await;
     let db = &app_reader.db;
-    let recipe_result = recipe::get_random_from_tags(db, tags).await;
-
-    match recipe_result {
-        Ok((recipe, tags)) => Ok(JSONRecipe::new(recipe, tags).into_response()),
+    let count = limit.unwrap_or(1).clamp(1, MAX_TAG_MATCHES);
+    let found = match recipe::find_by_tags(db, &query, count).await {
+        Ok(found) => found,
         Err(e) => {
             log::warn!("api:get_recipe_by_tag failed: {}", e);
-            Err(http::StatusCode::NOT_FOUND)
+            return Err(http::StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut recipes = Vec::with_capacity(found.len());
+    for (mut recipe, tags) in found {
+        let mut ingredients = ingredient::load(db, &recipe.id).await.map_err(|e| {
+            log::warn!("api:get_recipe_by_tag failed: {}", e);
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if let Some(system) = units.units {
+            recipe.convert_units(system);
+            ingredients = units::convert_all(&ingredients, system);
         }
+        recipes.push(JSONRecipe::new(recipe, tags, ingredients));
     }
+    let matches = match limit {
+        Some(_) => TagMatches::Many(recipes),
+        None => TagMatches::One(Box::new(recipes.pop().ok_or(http::StatusCode::NOT_FOUND)?)),
+    };
+    Ok(Json(matches).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(search::SearchParams),
+    responses(
+        (status = 200, description = "Ranked page of matching recipes", body = search::SearchResults),
+        (status = 400, description = "Query has no searchable words", body = String),
+    )
+)]
+pub async fn search_recipes(
+    State(app_state): State<SharedAppState>,
+    Query(params): Query<search::SearchParams>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match search::search(&app_state.db, &params).await {
+        Ok(results) => Json(results).into_response(),
+        Err(e @ RecipeError::EmptySearch) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            log::error!("api:search_recipes failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/recipes",
+    params(listing::ListParams),
+    responses(
+        (status = 200, description = "A page of recipes", body = listing::RecipePage),
+        (status = 400, description = "Invalid cursor or tag query", body = String),
+    )
+)]
+pub async fn list_recipes(
+    State(app_state): State<SharedAppState>,
+    Query(params): Query<listing::ListParams>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match listing::list(&app_state.db, &params).await {
+        Ok(page) => Json(page).into_response(),
+        Err(e @ (RecipeError::InvalidCursor | RecipeError::TagQuery(_))) => {
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        Err(e) => {
+            log::error!("api:list_recipes failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Record a user registering or logging in, as that user.
+async fn audit_user(
+    db: &SqlitePool,
+    request_id: &audit::RequestId,
+    action: &'static str,
+    user: &users::User,
+) {
+    let user_id = user.id.to_string();
+    let event = audit::Event::new(action)
+        .actor(&user_id)
+        .target(&user_id)
+        .after(serde_json::json!(user));
+    audit::record(db, request_id, event).await;
+}
+
+/// Record a failed login or registration against the email it named.
+async fn audit_failed_auth(
+    db: &SqlitePool,
+    request_id: &audit::RequestId,
+    addr: SocketAddr,
+    email: &str,
+    reason: String,
+) {
+    let event = audit::Event::new("auth.failed")
+        .target(email)
+        .after(serde_json::json!({ "ip": addr.ip(), "reason": reason }));
+    audit::record(db, request_id, event).await;
 }
 
 #[utoipa::path(
@@ -125,20 +821,687 @@ pub async fn get_recipe_by_tag(
     path = "/register",
     request_body(
         content = inline(authjwt::Registration),
-        description = "Get an API key",
+        description = "Get a token with an invite code",
     ),
     responses(
-        (status = 200, description = "JSON Web Token", body = authjwt::AuthBody),
-        (status = 401, description = "Registration failed", body = authjwt::AuthError),
+        (status = 200, description = "Access and refresh tokens", body = authjwt::AuthBody),
+        (status = 400, description = "Invalid name or email", body = String),
+        (status = 401, description = "Unknown, expired, revoked or used up invite, or one reserved for another email", body = String),
+        (status = 409, description = "Email is already registered; log in instead", body = String),
+        (status = 429, description = "Too many failed attempts; see Retry-After", body = authjwt::AuthError),
     )
 )]
 pub async fn register(
     State(app_state): State<SharedAppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_id: audit::RequestId,
     Json(registration): Json<authjwt::Registration>,
 ) -> axum::response::Response {
     let app_state = app_state.read().await;
-    match authjwt::make_jwt_token(&app_state, &registration) {
+    let attempt = throttle::Attempt::new(addr.ip(), &registration.email);
+    if let Err(e) = attempt.reserve(&app_state.db).await {
+        return e.into_response();
+    }
+    let user = match users::register(
+        &app_state.db,
+        &registration.full_name,
+        &registration.email,
+        &registration.access_code,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            if let UserError::InviteRejected = e {
+                audit_failed_auth(&app_state.db, &request_id, addr, &registration.email, e.to_string()).await;
+            } else {
+                attempt.release(&app_state.db).await;
+            }
+            return e.into_response();
+        }
+    };
+    attempt.succeeded(&app_state.db).await;
+    audit_user(&app_state.db, &request_id, "user.register", &user).await;
+    match authjwt::issue_tokens(&app_state, &user).await {
         Err(e) => e.into_response(),
         Ok(token) => (StatusCode::OK, token).into_response(),
     }
 }
+
+#[utoipa::path(
+    post,
+    path = "/users",
+    request_body(
+        content = inline(users::Signup),
+        description = "Create an account with a password",
+    ),
+    responses(
+        (status = 201, description = "Account created", body = users::User),
+        (status = 400, description = "Invalid email, name or password", body = String),
+        (status = 401, description = "Unknown, expired, revoked or used up invite, or one reserved for another email", body = String),
+        (status = 409, description = "Email is already registered", body = String),
+        (status = 429, description = "Too many failed attempts; see Retry-After", body = authjwt::AuthError),
+    )
+)]
+pub async fn signup(
+    State(app_state): State<SharedAppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_id: audit::RequestId,
+    Json(signup): Json<users::Signup>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let attempt = throttle::Attempt::new(addr.ip(), &signup.email);
+    if let Err(e) = attempt.reserve(&app_state.db).await {
+        return e.into_response();
+    }
+    let email = signup.email.clone();
+    match users::create(&app_state.db, signup).await {
+        Ok(user) => {
+            attempt.succeeded(&app_state.db).await;
+            audit_user(&app_state.db, &request_id, "user.signup", &user).await;
+            (StatusCode::CREATED, Json(user)).into_response()
+        }
+        Err(e) => {
+            if let UserError::InviteRejected = e {
+                audit_failed_auth(&app_state.db, &request_id, addr, &email, e.to_string()).await;
+            } else {
+                attempt.release(&app_state.db).await;
+            }
+            e.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/login",
+    request_body(
+        content = inline(users::Login),
+        description = "Exchange an email and password for a token",
+    ),
+    responses(
+        (status = 200, description = "Access and refresh tokens", body = authjwt::AuthBody),
+        (status = 401, description = "Invalid email or password", body = authjwt::AuthError),
+        (status = 429, description = "Too many failed attempts; see Retry-After", body = authjwt::AuthError),
+    )
+)]
+pub async fn login(
+    State(app_state): State<SharedAppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_id: audit::RequestId,
+    Json(login): Json<users::Login>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let attempt = throttle::Attempt::new(addr.ip(), &login.email);
+    if let Err(e) = attempt.reserve(&app_state.db).await {
+        return e.into_response();
+    }
+    let email = login.email.clone();
+    let user = match users::authenticate(&app_state.db, login).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let e = authjwt::AuthError::InvalidCredentials;
+            audit_failed_auth(&app_state.db, &request_id, addr, &email, e.to_string()).await;
+            return e.into_response();
+        }
+        Err(e) => {
+            attempt.release(&app_state.db).await;
+            return e.into_response();
+        }
+    };
+    attempt.succeeded(&app_state.db).await;
+    audit_user(&app_state.db, &request_id, "user.login", &user).await;
+    match authjwt::issue_tokens(&app_state, &user).await {
+        Err(e) => e.into_response(),
+        Ok(token) => (StatusCode::OK, token).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/token/refresh",
+    request_body(
+        content = inline(authjwt::RefreshRequest),
+        description = "Refresh token from login or the last refresh; each works once",
+    ),
+    responses(
+        (status = 200, description = "New access and refresh tokens", body = authjwt::AuthBody),
+        (status = 401, description = "Unknown, used, revoked or expired refresh token", body = authjwt::AuthError),
+    )
+)]
+pub async fn refresh_token(
+    State(app_state): State<SharedAppState>,
+    Json(request): Json<authjwt::RefreshRequest>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match authjwt::refresh_tokens(&app_state, &request.refresh_token).await {
+        Err(e) => e.into_response(),
+        Ok(token) => (StatusCode::OK, token).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    request_body(
+        content = Option<authjwt::RefreshRequest>,
+        description = "Refresh token to revoke along with the access token",
+    ),
+    responses(
+        (status = 204, description = "Tokens revoked"),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+    )
+)]
+pub async fn logout(
+    claims: authjwt::Claims,
+    State(app_state): State<SharedAppState>,
+    request_id: audit::RequestId,
+    request: Option<Json<authjwt::RefreshRequest>>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let db = &app_state.db;
+    let denied = match claims.jti.is_empty() {
+        true => Ok(()),
+        false => tokens::deny(db, &claims.jti, claims.exp as i64).await,
+    };
+    let revoked = match (request, claims.sub.parse()) {
+        (Some(Json(request)), Ok(user_id)) => tokens::revoke(db, &request.refresh_token, user_id).await,
+        _ => Ok(()),
+    };
+    match denied.and(revoked) {
+        Ok(()) => {
+            let event = audit::Event::new("user.logout").actor(&claims.sub).target(&claims.sub);
+            audit::record(db, &request_id, event).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            log::error!("api:logout failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    responses(
+        (status = 200, description = "Every user", body = [users::User]),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn list_users(
+    _claims: Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match users::list(&app_state.db).await {
+        Ok(users) => Json(users).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}/role",
+    params(("id" = i64, Path, description = "User id")),
+    request_body(
+        content = inline(users::RoleChange),
+        description = "Role the user gets in tokens issued from now on",
+    ),
+    responses(
+        (status = 200, description = "Updated user", body = users::User),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+        (status = 404, description = "No such user", body = String),
+    )
+)]
+pub async fn set_user_role(
+    Require(claims, _): Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+    Path(user_id): Path<i64>,
+    request_id: audit::RequestId,
+    Json(change): Json<users::RoleChange>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    let before = users::get(&app_state.db, user_id).await.ok();
+    match users::set_role(&app_state.db, user_id, change.role).await {
+        Ok(user) => {
+            let target = user_id.to_string();
+            let event = audit::Event::new("user.role")
+                .actor(&claims.sub)
+                .target(&target)
+                .before(before.map(|before| serde_json::json!(before)))
+                .after(serde_json::json!(user));
+            audit::record(&app_state.db, &request_id, event).await;
+            Json(user).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// User id of the caller, for endpoints that act on the caller's own records.
+fn user_id(claims: &authjwt::Claims) -> Result<i64, authjwt::AuthError> {
+    claims.sub.parse().map_err(|_| authjwt::AuthError::InvalidToken)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    request_body(
+        content = inline(apikeys::NewApiKey),
+        description = "Name, scopes and optional lifetime of the key",
+    ),
+    responses(
+        (status = 201, description = "Key created; the key is only shown now", body = apikeys::CreatedApiKey),
+        (status = 400, description = "Missing name or scopes", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Called with an API key", body = authjwt::AuthError),
+    )
+)]
+pub async fn create_api_key(
+    claims: authjwt::Claims,
+    State(app_state): State<SharedAppState>,
+    request_id: audit::RequestId,
+    Json(request): Json<apikeys::NewApiKey>,
+) -> axum::response::Response {
+    if claims.scopes.is_some() {
+        return authjwt::AuthError::ApiKeyNotAllowed.into_response();
+    }
+    let user_id = match user_id(&claims) {
+        Ok(user_id) => user_id,
+        Err(e) => return e.into_response(),
+    };
+    let app_state = app_state.read().await;
+    match apikeys::create(&app_state.db, user_id, request).await {
+        Ok(key) => {
+            let target = key.info.id.to_string();
+            let event = audit::Event::new("apikey.create")
+                .actor(&claims.sub)
+                .target(&target)
+                .after(serde_json::json!(key.info));
+            audit::record(&app_state.db, &request_id, event).await;
+            (StatusCode::CREATED, Json(key)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    responses(
+        (status = 200, description = "The caller's API keys", body = [apikeys::ApiKey]),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Called with an API key", body = authjwt::AuthError),
+    )
+)]
+pub async fn list_api_keys(
+    claims: authjwt::Claims,
+    State(app_state): State<SharedAppState>,
+) -> axum::response::Response {
+    if claims.scopes.is_some() {
+        return authjwt::AuthError::ApiKeyNotAllowed.into_response();
+    }
+    let user_id = match user_id(&claims) {
+        Ok(user_id) => user_id,
+        Err(e) => return e.into_response(),
+    };
+    let app_state = app_state.read().await;
+    match apikeys::list(&app_state.db, user_id).await {
+        Ok(keys) => Json(keys).into_response(),
+        Err(e) => {
+            log::error!("api:list_api_keys failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{key_id}",
+    params(("key_id" = i64, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Called with an API key", body = authjwt::AuthError),
+        (status = 404, description = "No such key"),
+    )
+)]
+pub async fn revoke_api_key(
+    claims: authjwt::Claims,
+    State(app_state): State<SharedAppState>,
+    Path(key_id): Path<i64>,
+    request_id: audit::RequestId,
+) -> axum::response::Response {
+    if claims.scopes.is_some() {
+        return authjwt::AuthError::ApiKeyNotAllowed.into_response();
+    }
+    let user_id = match user_id(&claims) {
+        Ok(user_id) => user_id,
+        Err(e) => return e.into_response(),
+    };
+    let app_state = app_state.read().await;
+    match apikeys::revoke(&app_state.db, user_id, key_id).await {
+        Ok(()) => {
+            let target = key_id.to_string();
+            let event = audit::Event::new("apikey.revoke").actor(&claims.sub).target(&target);
+            audit::record(&app_state.db, &request_id, event).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => write_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/invites",
+    request_body(
+        content = inline(invites::NewInvite),
+        description = "How many registrations the code allows and how long it lasts",
+    ),
+    responses(
+        (status = 201, description = "Invite minted; the code is only shown now", body = invites::CreatedInvite),
+        (status = 400, description = "Invalid use count, lifetime or email", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn create_invite(
+    Require(claims, _): Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+    request_id: audit::RequestId,
+    Json(request): Json<invites::NewInvite>,
+) -> axum::response::Response {
+    let user_id = match user_id(&claims) {
+        Ok(user_id) => user_id,
+        Err(e) => return e.into_response(),
+    };
+    let app_state = app_state.read().await;
+    match invites::create(&app_state.db, Some(user_id), request).await {
+        Ok(invite) => {
+            let target = invite.info.id.to_string();
+            let event = audit::Event::new("invite.create")
+                .actor(&claims.sub)
+                .target(&target)
+                .after(serde_json::json!(invite.info));
+            audit::record(&app_state.db, &request_id, event).await;
+            (StatusCode::CREATED, Json(invite)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/invites",
+    responses(
+        (status = 200, description = "Every invite and who registered with it", body = [invites::Invite]),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn list_invites(
+    _claims: Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match invites::list(&app_state.db).await {
+        Ok(invites) => Json(invites).into_response(),
+        Err(e) => {
+            log::error!("api:list_invites failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/invites/{invite_id}",
+    params(("invite_id" = i64, Path, description = "Invite id")),
+    responses(
+        (status = 204, description = "Invite revoked"),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+        (status = 404, description = "No such live invite"),
+    )
+)]
+pub async fn revoke_invite(
+    Require(claims, _): Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+    Path(invite_id): Path<i64>,
+    request_id: audit::RequestId,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match invites::revoke(&app_state.db, invite_id).await {
+        Ok(()) => {
+            let target = invite_id.to_string();
+            let event = audit::Event::new("invite.revoke").actor(&claims.sub).target(&target);
+            audit::record(&app_state.db, &request_id, event).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => write_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/lockouts",
+    responses(
+        (status = 200, description = "Client IPs and emails locked out by failed attempts", body = [throttle::Lockout]),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn list_lockouts(
+    _claims: Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match throttle::locked(&app_state.db).await {
+        Ok(lockouts) => Json(lockouts).into_response(),
+        Err(e) => {
+            log::error!("api:list_lockouts failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/lockouts/{kind}/{identity}",
+    params(
+        ("kind" = throttle::Kind, Path, description = "`ip` or `email`"),
+        ("identity" = String, Path, description = "Client IP or email"),
+    ),
+    responses(
+        (status = 204, description = "Failures forgotten and any lockout lifted"),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+        (status = 404, description = "No failures recorded for it"),
+    )
+)]
+pub async fn clear_lockout(
+    Require(claims, _): Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+    Path((kind, identity)): Path<(throttle::Kind, String)>,
+    request_id: audit::RequestId,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match throttle::clear(&app_state.db, kind, &identity).await {
+        Ok(()) => {
+            let event = audit::Event::new("lockout.clear")
+                .actor(&claims.sub)
+                .target(&identity)
+                .after(serde_json::json!({ "kind": kind }));
+            audit::record(&app_state.db, &request_id, event).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => write_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(audit::AuditParams),
+    responses(
+        (status = 200, description = "A page of audit entries, newest first", body = audit::AuditPage),
+        (status = 400, description = "Invalid since or until time", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Not an admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn get_audit_log(
+    _claims: Require<roles::Admin>,
+    State(app_state): State<SharedAppState>,
+    Query(params): Query<audit::AuditParams>,
+) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    match audit::query(&app_state.db, &params).await {
+        Ok(page) => Json(page).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/export",
+    params(export::ExportParams),
+    responses(
+        (status = 200, description = "Every recipe, streamed as a JSON array or as NDJSON", content(
+            ([JSONRecipe] = "application/json"),
+            (JSONRecipe = "application/x-ndjson"),
+        )),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "API key lacks recipes:read", body = authjwt::AuthError),
+    )
+)]
+pub async fn export_recipes(
+    _claims: Require<roles::Viewer>,
+    State(app_state): State<SharedAppState>,
+    Query(params): Query<export::ExportParams>,
+) -> axum::response::Response {
+    let db = app_state.read().await.db.clone();
+    let format = params.format.unwrap_or_default();
+    let body = axum::body::Body::from_stream(export::chunks(db, format));
+    ([(http::header::CONTENT_TYPE, format.content_type())], body).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/import",
+    params(import::ImportParams),
+    request_body(
+        content = [JSONRecipe],
+        description = "Recipes in the format `/export` returns as JSON"
+    ),
+    responses(
+        (status = 200, description = "What happened, or would happen, to each recipe", body = import::ImportReport),
+        (status = 400, description = "Bad request", body = String),
+        (status = 401, description = "Auth error", body = authjwt::AuthError),
+        (status = 403, description = "Role too low for the mode: upsert needs editor, replace needs admin", body = authjwt::AuthError),
+    )
+)]
+pub async fn import_recipes(
+    Require(claims, _): Require<roles::Contributor>,
+    State(app_state): State<SharedAppState>,
+    Query(params): Query<import::ImportParams>,
+    request_id: audit::RequestId,
+    Json(recipes): Json<Vec<JSONRecipe>>,
+) -> axum::response::Response {
+    let mode = params.mode.unwrap_or_default();
+    if claims.role < mode.min_role() {
+        return authjwt::AuthError::Forbidden(mode.min_role()).into_response();
+    }
+    if mode == import::Mode::Replace && !claims.has_scope(apikeys::Scope::Admin) {
+        return authjwt::AuthError::MissingScope(apikeys::Scope::Admin).into_response();
+    }
+    let app_state = app_state.read().await;
+    let importer = import::Importer {
+        author: &claims.sub,
+        owner: Some(&claims.sub),
+        request_id: Some(&request_id),
+    };
+    match import::run(&app_state.db, &recipes, mode, params.dry_run, &importer).await {
+        Ok(report) => {
+            if !report.dry_run {
+                let event = audit::Event::new("recipe.import")
+                    .actor(&claims.sub)
+                    .after(serde_json::json!({ "mode": report.mode, "summary": report.summary }));
+                audit::record(&app_state.db, &request_id, event).await;
+            }
+            Json(report).into_response()
+        }
+        Err(e) => {
+            log::error!("import: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `GET /recipe/pancakes` with the given `Accept` header: status, content type and ETag.
+    async fn get_pancakes(accept: Option<&str>) -> (StatusCode, String, String) {
+        let db = test_db().await;
+        let recipe = serde_json::from_value(serde_json::json!({
+            "id": "pancakes",
+            "title": "Pancakes",
+            "ingredients": "2 cups flour\n2 eggs",
+            "instructions": "Mix the flour and eggs.\nFry.",
+            "source": "",
+            "tags": [],
+        }))
+        .expect("valid recipe");
+        recipe::add(&db, recipe, "tester").await.expect("add");
+        let token_config = authjwt::TokenConfig {
+            issuer: "test".to_string(),
+            audience: "test".to_string(),
+            leeway: 0,
+        };
+        let state = AppState::new(db, jwks::JwtKeys::new(b"test secret"), token_config);
+
+        let mut headers = http::HeaderMap::new();
+        if let Some(accept) = accept {
+            headers.insert(http::header::ACCEPT, accept.parse().expect("valid header"));
+        }
+        let response = get_recipe_by_id(
+            State(Arc::new(RwLock::new(state))),
+            Path("pancakes".to_string()),
+            Query(Default::default()),
+            Query(Default::default()),
+            None,
+            headers,
+        )
+        .await
+        .expect("recipe found");
+        let header = |name| {
+            let value = response.headers().get(name).and_then(|value| value.to_str().ok());
+            value.unwrap_or_default().to_string()
+        };
+        (response.status(), header(http::header::CONTENT_TYPE), header(http::header::ETAG))
+    }
+
+    #[tokio::test]
+    async fn recipe_representation_follows_accept_weights() {
+        let cases = [
+            (None, "application/json", "\"1\""),
+            (Some("application/json, application/ld+json;q=0.1"), "application/json", "\"1\""),
+            (Some("application/json;q=0.5, application/ld+json"), "application/ld+json", "\"1-ld\""),
+            (Some("application/ld+json;q=0, */*"), "application/json", "\"1\""),
+            (Some("application/ld+json, */*;q=0.8"), "application/ld+json", "\"1-ld\""),
+            (Some("text/*"), "text/x-cooklang; charset=utf-8", "\"1-cook\""),
+            (Some("text/html, text/x-cooklang;q=0.9, */*;q=0.1"), "text/x-cooklang; charset=utf-8", "\"1-cook\""),
+            (Some("image/png"), "application/json", "\"1\""),
+        ];
+        for (accept, content_type, etag) in cases {
+            let (status, got_type, got_etag) = get_pancakes(accept).await;
+            assert_eq!(status, StatusCode::OK, "{:?}", accept);
+            assert_eq!(got_type, content_type, "{:?}", accept);
+            assert_eq!(got_etag, etag, "{:?}", accept);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::*;
+
+/// Every API key starts with this, so it can be told apart from a JWT.
+pub const KEY_PREFIX: &str = "rsk_";
+
+/// What an API key may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    #[serde(rename = "recipes:read")]
+    RecipesRead,
+    #[serde(rename = "recipes:write")]
+    RecipesWrite,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::RecipesRead => "recipes:read",
+            Scope::RecipesWrite => "recipes:write",
+            Scope::Admin => "admin",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [Scope::RecipesRead, Scope::RecipesWrite, Scope::Admin]
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Scopes are stored as one space separated column.
+fn join_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+fn split_scopes(scopes: &str) -> Vec<Scope> {
+    scopes.split_whitespace().filter_map(Scope::parse).collect()
+}
+
+/// Request to create an API key.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct NewApiKey {
+    #[schema(example = "nightly import")]
+    pub name: String,
+    #[schema(example = json!(["recipes:read", "recipes:write"]))]
+    pub scopes: Vec<Scope>,
+    /// Days until the key stops working; it never expires when left out.
+    pub expires_in_days: Option<i64>,
+}
+
+/// An API key as listed; the key itself is only shown when created.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// First characters of the key, to tell keys apart.
+    #[schema(example = "rsk_3f9a1c")]
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+/// A freshly created API key, including the secret.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// Send as `X-Api-Key` or as a Bearer token. It cannot be shown again.
+    #[schema(example = "rsk_3f9a1c...")]
+    pub key: String,
+    #[serde(flatten)]
+    pub info: ApiKey,
+}
+
+/// Create an API key for a user.
+pub async fn create(
+    db: &SqlitePool,
+    user_id: i64,
+    request: NewApiKey,
+) -> Result<CreatedApiKey, UserError> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(UserError::InvalidApiKey("name is required"));
+    }
+    if request.scopes.is_empty() {
+        return Err(UserError::InvalidApiKey("at least one scope is required"));
+    }
+    let expires_at = match request.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(UserError::InvalidApiKey("expires_in_days must be positive"));
+        }
+        Some(days) => {
+            let expires_at = TimeDelta::try_days(days)
+                .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+                .ok_or(UserError::InvalidApiKey("expires_in_days is too large"))?;
+            Some(expires_at.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        }
+        None => None,
+    };
+
+    let key = format!("{}{}", KEY_PREFIX, tokens::random_hex(32));
+    let key_hash = tokens::hash(&key);
+    let prefix = key[..KEY_PREFIX.len() + 6].to_string();
+    let scopes = join_scopes(&request.scopes);
+    let row = sqlx::query!(
+        r#"INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), $6)
+        RETURNING id AS "id!", created_at;"#,
+        user_id,
+        name,
+        prefix,
+        key_hash,
+        scopes,
+        expires_at,
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(CreatedApiKey {
+        key,
+        info: ApiKey {
+            id: row.id,
+            name: name.to_string(),
+            prefix,
+            scopes: request.scopes,
+            created_at: row.created_at,
+            expires_at,
+            last_used_at: None,
+        },
+    })
+}
+
+/// A user's keys that have not been revoked, newest first.
+pub async fn list(db: &SqlitePool, user_id: i64) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id AS "id!", name, prefix, scopes, created_at, expires_at, last_used_at
+        FROM api_keys WHERE user_id = $1 AND revoked_at IS NULL ORDER BY id DESC;"#,
+        user_id,
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ApiKey {
+            id: row.id,
+            name: row.name,
+            prefix: row.prefix,
+            scopes: split_scopes(&row.scopes),
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        })
+        .collect())
+}
+
+/// Revoke one of a user's keys. Returns `RowNotFound` for keys they do not have.
+pub async fn revoke(db: &SqlitePool, user_id: i64, key_id: i64) -> Result<(), sqlx::Error> {
+    let revoked = sqlx::query!(
+        r#"UPDATE api_keys SET revoked_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL;"#,
+        key_id,
+        user_id,
+    )
+    .execute(db)
+    .await?;
+    if revoked.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Look up a live key, recording its use, and return its owner, role and scopes.
+pub async fn authenticate(
+    db: &SqlitePool,
+    key: &str,
+) -> Result<Option<(i64, Role, Vec<Scope>)>, sqlx::Error> {
+    let key_hash = tokens::hash(key);
+    let row = sqlx::query!(
+        r#"UPDATE api_keys SET last_used_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE key_hash = $1 AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        RETURNING user_id, scopes,
+            (SELECT role FROM users WHERE users.id = api_keys.user_id) AS "role!: Role";"#,
+        key_hash,
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(|row| (row.user_id, row.role, split_scopes(&row.scopes))))
+}
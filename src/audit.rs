@@ -0,0 +1,360 @@
+//! Append-only record of who wrote what and of authentication events.
+//!
+//! Entries are written after the change they describe; a failure to record one
+//! is logged but does not fail the request.
+
+use crate::*;
+
+use sqlx::{QueryBuilder, Sqlite};
+
+/// Entries per page when no limit is given.
+const DEFAULT_LIMIT: i64 = 50;
+/// Largest page the audit log will return.
+const MAX_LIMIT: i64 = 200;
+/// Rejected or refused requests recorded per client IP and action each minute;
+/// the rest are counted and recorded as one summary entry once the minute is
+/// over, so a flood of bad tokens cannot grow the log without bound.
+const MAX_REJECTIONS_PER_MINUTE: i64 = 60;
+
+/// Header the client's own `X-Request-Id` is moved to.
+const CLIENT_REQUEST_ID: &str = "x-client-request-id";
+
+/// Ids of the request being handled: the `X-Request-Id` the server generated
+/// for it, and the one the client sent, if any.
+#[derive(Debug, Clone, Default)]
+pub struct RequestId {
+    pub id: Option<String>,
+    pub client: Option<String>,
+}
+
+impl RequestId {
+    pub fn from_headers(headers: &http::HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        RequestId {
+            id: header("x-request-id"),
+            client: header(CLIENT_REQUEST_ID),
+        }
+    }
+}
+
+/// Move a client's `X-Request-Id` aside, so the server always generates the
+/// id it logs and audits under and a client cannot pass off another request's.
+pub async fn take_client_request_id(mut request: axum::extract::Request) -> axum::extract::Request {
+    let headers = request.headers_mut();
+    headers.remove(CLIENT_REQUEST_ID);
+    if let Some(client) = headers.remove("x-request-id") {
+        headers.insert(CLIENT_REQUEST_ID, client);
+    }
+    request
+}
+
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for RequestId {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(RequestId::from_headers(&parts.headers))
+    }
+}
+
+/// Something to record, such as `recipe.update` by user 42 on `pancakes`.
+pub struct Event<'a> {
+    action: &'static str,
+    actor: Option<&'a str>,
+    target: Option<&'a str>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            actor: None,
+            target: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// User id of whoever did it.
+    pub fn actor(mut self, actor: &'a str) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Id of the recipe, user, key or invite it was done to.
+    pub fn target(mut self, target: &'a str) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// State before the change.
+    pub fn before(mut self, before: Option<serde_json::Value>) -> Self {
+        self.before = before;
+        self
+    }
+
+    /// State after the change, or details of a failed attempt.
+    pub fn after(mut self, after: serde_json::Value) -> Self {
+        self.after = Some(after);
+        self
+    }
+}
+
+/// Append an event to the audit log.
+pub async fn record(db: &SqlitePool, request_id: &RequestId, event: Event<'_>) {
+    let before = event.before.map(|value| value.to_string());
+    let after = event.after.map(|value| value.to_string());
+    let inserted = sqlx::query!(
+        r#"INSERT INTO audit_log
+        (at, actor, action, target, request_id, client_request_id, before_json, after_json)
+        VALUES (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), $1, $2, $3, $4, $5, $6, $7);"#,
+        event.actor,
+        event.action,
+        event.target,
+        request_id.id,
+        request_id.client,
+        before,
+        after,
+    )
+    .execute(db)
+    .await;
+    if let Err(e) = inserted {
+        log::error!("audit: recording {}: {}", event.action, e);
+    }
+}
+
+/// IP address the request came from, when the server knows it.
+pub fn client_ip(parts: &http::request::Parts) -> Option<std::net::IpAddr> {
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// Append a rejected or refused request from `ip` to the audit log, adding the
+/// IP to its details.
+///
+/// Past [`MAX_REJECTIONS_PER_MINUTE`] of the same action from the same IP in
+/// the last minute, the request is only counted; each minute's count is
+/// recorded as one entry under the same action, with the IP and the number of
+/// requests it stands for, once that minute is over.
+pub async fn record_rejection(
+    db: &SqlitePool,
+    request_id: &RequestId,
+    ip: Option<std::net::IpAddr>,
+    mut event: Event<'_>,
+) {
+    record_overflow(db).await;
+    let ip = ip.map(|ip| ip.to_string());
+    if let Some(serde_json::Value::Object(after)) = &mut event.after {
+        after.insert("ip".to_string(), serde_json::json!(ip));
+    }
+    let recent = sqlx::query_scalar!(
+        r#"SELECT count(*) FROM audit_log
+        WHERE action = $1 AND json_extract(after_json, '$.ip') IS $2
+        AND at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now', '-60 seconds');"#,
+        event.action,
+        ip,
+    )
+    .fetch_one(db)
+    .await;
+    match recent {
+        Ok(recent) if recent >= MAX_REJECTIONS_PER_MINUTE => {
+            let counted = sqlx::query!(
+                r#"INSERT INTO audit_overflow (action, ip, minute, suppressed)
+                VALUES ($1, $2, strftime('%Y-%m-%dT%H:%M:00Z', 'now'), 1)
+                ON CONFLICT (action, ip, minute) DO UPDATE SET suppressed = suppressed + 1;"#,
+                event.action,
+                ip,
+            )
+            .execute(db)
+            .await;
+            if let Err(e) = counted {
+                log::error!("audit: counting {} over the limit: {}", event.action, e);
+            }
+        }
+        Ok(_) => record(db, request_id, event).await,
+        Err(e) => log::error!("audit: counting {}: {}", event.action, e),
+    }
+}
+
+/// Record the counts of rejected requests over the limit in minutes that are
+/// over, one entry per action, IP and minute.
+async fn record_overflow(db: &SqlitePool) {
+    let recorded = async {
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO audit_log (at, action, after_json)
+            SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), action,
+                json_object('ip', ip, 'minute', minute, 'suppressed', suppressed)
+            FROM audit_overflow WHERE minute < strftime('%Y-%m-%dT%H:%M:00Z', 'now')
+            ORDER BY minute;"#
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM audit_overflow WHERE minute < strftime('%Y-%m-%dT%H:%M:00Z', 'now');"
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+    .await;
+    if let Err(e) = recorded {
+        log::error!("audit: recording rejections over the limit: {}", e);
+    }
+}
+
+/// Query parameters for reading the audit log, newest entries first.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct AuditParams {
+    /// Only entries by this user id.
+    pub actor: Option<String>,
+    /// Only this action, or actions starting with it when it ends in `*`, such as `recipe.*`.
+    pub action: Option<String>,
+    /// Only entries at or after this RFC 3339 time.
+    pub since: Option<String>,
+    /// Only entries before this RFC 3339 time.
+    pub until: Option<String>,
+    /// Entries per page, at most 200.
+    pub limit: Option<i64>,
+    /// `next` cursor from the previous page.
+    pub cursor: Option<i64>,
+}
+
+/// One recorded event.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    #[schema(example = "2025-06-01T12:00:00Z")]
+    pub at: String,
+    #[schema(example = "42")]
+    pub actor: Option<String>,
+    #[schema(example = "recipe.update")]
+    pub action: String,
+    #[schema(example = "pancakes")]
+    pub target: Option<String>,
+    pub request_id: Option<String>,
+    /// `X-Request-Id` the client sent, as the client claimed it.
+    pub client_request_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// One page of the audit log.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    /// Cursor for the following page, absent on the last page.
+    pub next: Option<i64>,
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(sqlx::FromRow)]
+struct AuditRow {
+    id: i64,
+    at: String,
+    actor: Option<String>,
+    action: String,
+    target: Option<String>,
+    request_id: Option<String>,
+    client_request_id: Option<String>,
+    before_json: Option<String>,
+    after_json: Option<String>,
+}
+
+/// A time parameter in the form `at` is stored in, so they compare as text.
+fn normalize_time(name: &'static str, time: &str) -> Result<String, AuditError> {
+    let time = DateTime::parse_from_rfc3339(time).map_err(|_| AuditError::InvalidTime(name))?;
+    Ok(time.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// A page of audit entries matching the filters.
+pub async fn query(db: &SqlitePool, params: &AuditParams) -> Result<AuditPage, AuditError> {
+    record_overflow(db).await;
+    let since = params
+        .since
+        .as_deref()
+        .map(|time| normalize_time("since", time))
+        .transpose()?;
+    let until = params
+        .until
+        .as_deref()
+        .map(|time| normalize_time("until", time))
+        .transpose()?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(
+        "SELECT id, at, actor, action, target, request_id, client_request_id, before_json, after_json \
+        FROM audit_log WHERE 1 = 1",
+    );
+    if let Some(actor) = &params.actor {
+        builder.push(" AND actor = ").push_bind(actor.clone());
+    }
+    match params.action.as_deref().map(|action| action.strip_suffix('*')) {
+        Some(Some(prefix)) => {
+            builder
+                .push(" AND action LIKE ")
+                .push_bind(format!("{}%", listing::like_literal(prefix)))
+                .push(" ESCAPE '\\'");
+        }
+        Some(None) => {
+            builder.push(" AND action = ").push_bind(params.action.clone());
+        }
+        None => (),
+    }
+    if let Some(since) = since {
+        builder.push(" AND at >= ").push_bind(since);
+    }
+    if let Some(until) = until {
+        builder.push(" AND at < ").push_bind(until);
+    }
+    if let Some(cursor) = params.cursor {
+        builder.push(" AND id < ").push_bind(cursor);
+    }
+    builder.push(" ORDER BY id DESC LIMIT ").push_bind(limit + 1);
+
+    let mut rows: Vec<AuditRow> = builder.build_query_as().fetch_all(db).await?;
+    let next = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| row.id)
+    } else {
+        None
+    };
+    let json = |value: Option<String>| value.and_then(|value| serde_json::from_str(&value).ok());
+    let entries = rows
+        .into_iter()
+        .map(|row| AuditEntry {
+            id: row.id,
+            at: row.at,
+            actor: row.actor,
+            action: row.action,
+            target: row.target,
+            request_id: row.request_id,
+            client_request_id: row.client_request_id,
+            before: json(row.before_json),
+            after: json(row.after_json),
+        })
+        .collect();
+    Ok(AuditPage { next, entries })
+}
+
+impl IntoResponse for AuditError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            AuditError::InvalidTime(_) => (StatusCode::BAD_REQUEST, self.to_string()).into_response(),
+            AuditError::Database(e) => {
+                log::error!("audit: query failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
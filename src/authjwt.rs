@@ -2,19 +2,11 @@
 
 use crate::*;
 
-pub struct JwtKeys {
-    encoding: EncodingKey,
-    decoding: DecodingKey,
-}
+/// How long an access token is valid; refresh tokens renew it.
+const ACCESS_TTL: TimeDelta = TimeDelta::minutes(15);
 
-impl JwtKeys {
-    pub fn new(secret: &[u8]) -> Self {
-        Self {
-            encoding: EncodingKey::from_secret(secret),
-            decoding: DecodingKey::from_secret(secret),
-        }
-    }
-}
+/// Seconds of clock skew allowed on `exp`, `nbf` and `iat` unless `JWT_LEEWAY_SECS` says otherwise.
+const DEFAULT_LEEWAY: u64 = 60;
 
 /// Read secret from file from given env path
 pub async fn read_secret(env_var: &str, default: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -23,25 +15,77 @@ pub async fn read_secret(env_var: &str, default: &str) -> Result<String, Box<dyn
     Ok(secret.trim().to_string())
 }
 
-pub async fn make_jwt_keys() -> Result<JwtKeys, Box<dyn std::error::Error>> {
-    let secret = read_secret("JWT_SECRETFILE", "secrets/jwt_secret.txt").await?;
-    Ok(JwtKeys::new(secret.as_bytes()))
+/// Who issues our tokens and who they are for, as checked on every request.
+pub struct TokenConfig {
+    pub issuer: String,
+    pub audience: String,
+    pub leeway: u64,
+}
+
+impl TokenConfig {
+    /// Read `JWT_ISSUER`, `JWT_AUDIENCE` and `JWT_LEEWAY_SECS`, with defaults.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let issuer = std::env::var("JWT_ISSUER").unwrap_or_else(|_| "recipe-server.po8.org".to_owned());
+        let audience = std::env::var("JWT_AUDIENCE").unwrap_or_else(|_| "recipe-server".to_owned());
+        let leeway = match std::env::var("JWT_LEEWAY_SECS") {
+            Ok(secs) => secs.parse()?,
+            Err(_) => DEFAULT_LEEWAY,
+        };
+        Ok(Self { issuer, audience, leeway })
+    }
 }
 
 #[derive(Debug, thiserror::Error, Serialize)]
 pub enum AuthError {
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Token has expired")]
+    TokenExpired,
+    #[error("Token is not valid yet")]
+    TokenNotYetValid,
+    #[error("Token signature does not verify")]
+    BadSignature,
+    #[error("Token was issued by someone else")]
+    WrongIssuer,
+    #[error("Token is meant for another audience")]
+    WrongAudience,
     #[error("Internal error: token creation")]
     TokenCreation,
-    #[error("Registration error")]
-    Registration,
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("Invalid refresh token")]
+    InvalidRefreshToken,
+    #[error("Requires the {0} role")]
+    Forbidden(Role),
+    #[error("Only the owner or an editor may change this recipe")]
+    NotOwner,
+    #[error("API key lacks the {0} scope")]
+    MissingScope(apikeys::Scope),
+    #[error("API keys cannot manage API keys")]
+    ApiKeyNotAllowed,
+    #[error("Too many failed attempts, retry in {0} seconds")]
+    TooManyAttempts(i64),
+}
+
+impl From<jsonwebtoken::errors::Error> for AuthError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+
+        match e.kind() {
+            ErrorKind::ExpiredSignature => AuthError::TokenExpired,
+            ErrorKind::ImmatureSignature => AuthError::TokenNotYetValid,
+            ErrorKind::InvalidSignature => AuthError::BadSignature,
+            ErrorKind::InvalidIssuer => AuthError::WrongIssuer,
+            ErrorKind::InvalidAudience => AuthError::WrongAudience,
+            _ => AuthError::InvalidToken,
+        }
+    }
 }
 
 impl utoipa::PartialSchema for AuthError {
     fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::Schema> {
         serde_json::json!({
-            "status":"401","error":"Invalid Credentials"
+            "status":"401","error":"Token has expired","reason":"expired"
         }).into()
     }
 }
@@ -52,6 +96,11 @@ impl utoipa::ToSchema for AuthError {}
 pub struct AuthBody {
     access_token: String,
     token_type: String,
+    /// Seconds until the access token expires.
+    expires_in: i64,
+    /// Single-use token for `/token/refresh` to get the next access token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 impl AuthBody {
@@ -59,10 +108,18 @@ impl AuthBody {
         Self {
             access_token,
             token_type: "Bearer".to_string(),
+            expires_in: ACCESS_TTL.num_seconds(),
+            refresh_token: None,
         }
     }
 }
 
+/// Refresh token to trade in, or to revoke at logout.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
 impl IntoResponse for AuthBody {
     fn into_response(self) -> axum::response::Response {
         Json(serde_json::json!(self)).into_response()
@@ -73,33 +130,124 @@ impl axum::extract::FromRequestParts<SharedAppState> for Claims {
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut http::request::Parts, state: &SharedAppState) -> Result<Self, Self::Rejection> {
-        use jsonwebtoken::{Algorithm, Validation, decode};
-
         // Extract the token from the authorization header
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| AuthError::InvalidToken)?;
-        // Decode the user data
+        let bearer = parts.extract::<TypedHeader<Authorization<Bearer>>>().await.ok();
         let app_state = state.read().await;
-        let decoding_key = &app_state.jwt_keys.decoding;
-        let validation = Validation::new(Algorithm::HS512);
-        let result = decode::<Claims>(
-            bearer.token(),
-            decoding_key,
-            &validation,
-        );
-        let token_data = result.map_err(|_| AuthError::Registration)?;
-        Ok(token_data.claims)
+        let result = verify(parts, bearer, &app_state).await;
+        if let Err(e) = &result {
+            let request_id = audit::RequestId::from_headers(&parts.headers);
+            let event = audit::Event::new("auth.rejected")
+                .target(parts.uri.path())
+                .after(serde_json::json!({ "reason": e.to_string() }));
+            audit::record_rejection(&app_state.db, &request_id, audit::client_ip(parts), event).await;
+        }
+        result
+    }
+}
+
+/// Claims of the request's API key or bearer token, if it checks out.
+async fn verify(
+    parts: &http::request::Parts,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    app_state: &AppState,
+) -> Result<Claims, AuthError> {
+    use jsonwebtoken::{Validation, decode, decode_header};
+
+    // API keys come as X-Api-Key or as a Bearer token with the key prefix
+    let api_key = match parts.headers.get("x-api-key") {
+        Some(value) => Some(value.to_str().map_err(|_| AuthError::InvalidToken)?),
+        None => bearer
+            .as_ref()
+            .map(|TypedHeader(Authorization(bearer))| bearer.token())
+            .filter(|token| token.starts_with(apikeys::KEY_PREFIX)),
+    };
+    if let Some(key) = api_key {
+        return match apikeys::authenticate(&app_state.db, key).await {
+            Ok(Some((user_id, role, scopes))) => {
+                Ok(Claims::api_key(&app_state.token_config, user_id, role, scopes))
+            }
+            Ok(None) => Err(AuthError::InvalidToken),
+            Err(e) => {
+                log::error!("authjwt: checking API key: {}", e);
+                Err(AuthError::InvalidToken)
+            }
+        };
+    }
+    let TypedHeader(Authorization(bearer)) = bearer.ok_or(AuthError::InvalidToken)?;
+
+    // Find the key by the token's kid; the key alone decides the algorithm
+    let header = decode_header(bearer.token()).map_err(|_| AuthError::InvalidToken)?;
+    let key = app_state
+        .jwt_keys
+        .verifying_key(header.kid.as_deref())
+        .ok_or(AuthError::InvalidToken)?;
+    // Decode the user data, checking who issued it and who it is for
+    let config = &app_state.token_config;
+    let mut validation = Validation::new(key.algorithm);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+    validation.validate_nbf = true;
+    validation.leeway = config.leeway;
+    let token_data = decode::<Claims>(bearer.token(), &key.key, &validation)?;
+    let claims = token_data.claims;
+    // jsonwebtoken does not look at iat; a token issued in the future is not ours
+    let now = Utc::now().timestamp().max(0) as u64;
+    if claims.iat > now + config.leeway {
+        return Err(AuthError::TokenNotYetValid);
+    }
+    if !claims.jti.is_empty() {
+        match tokens::is_denied(&app_state.db, &claims.jti).await {
+            Ok(false) => (),
+            Ok(true) => return Err(AuthError::InvalidToken),
+            Err(e) => {
+                log::error!("authjwt: checking revoked tokens: {}", e);
+                return Err(AuthError::InvalidToken);
+            }
+        }
     }
+    Ok(claims)
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> axum::response::Response {
         let (status, error_message) = match self {
-            AuthError::Registration => (StatusCode::UNAUTHORIZED, "Invalid registration"),
             AuthError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"),
-            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
+            AuthError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Invalid email or password"),
+            AuthError::InvalidRefreshToken => (StatusCode::UNAUTHORIZED, "Invalid refresh token"),
+            AuthError::NotOwner => (StatusCode::FORBIDDEN, "Not the recipe's owner"),
+            AuthError::ApiKeyNotAllowed => (StatusCode::FORBIDDEN, "API keys cannot manage API keys"),
+            AuthError::MissingScope(scope) => {
+                let body = Json(serde_json::json!({
+                    "status": StatusCode::FORBIDDEN.as_u16(),
+                    "error": "Missing API key scope",
+                    "required_scope": scope,
+                }));
+                return (StatusCode::FORBIDDEN, body).into_response();
+            }
+            AuthError::InvalidToken
+            | AuthError::TokenExpired
+            | AuthError::TokenNotYetValid
+            | AuthError::BadSignature
+            | AuthError::WrongIssuer
+            | AuthError::WrongAudience => return token_rejection(self),
+            AuthError::TooManyAttempts(retry_after) => {
+                let body = Json(serde_json::json!({
+                    "status": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                    "error": "Too many failed attempts",
+                    "retry_after": retry_after,
+                }));
+                let headers = [(http::header::RETRY_AFTER, retry_after.to_string())];
+                return (StatusCode::TOO_MANY_REQUESTS, headers, body).into_response();
+            }
+            AuthError::Forbidden(role) => {
+                let body = Json(serde_json::json!({
+                    "status": StatusCode::FORBIDDEN.as_u16(),
+                    "error": "Insufficient role",
+                    "required_role": role,
+                }));
+                return (StatusCode::FORBIDDEN, body).into_response();
+            }
         };
         let body = Json(serde_json::json!({
             "status": status.as_u16(),
@@ -109,40 +257,134 @@ impl IntoResponse for AuthError {
     }
 }
 
+/// 401 for a rejected bearer token, with a machine-readable `reason` and the
+/// RFC 6750 challenge.
+fn token_rejection(e: AuthError) -> axum::response::Response {
+    let reason = match e {
+        AuthError::TokenExpired => "expired",
+        AuthError::TokenNotYetValid => "not_yet_valid",
+        AuthError::BadSignature => "bad_signature",
+        AuthError::WrongIssuer => "wrong_issuer",
+        AuthError::WrongAudience => "wrong_audience",
+        _ => "invalid",
+    };
+    let challenge = format!("Bearer error=\"invalid_token\", error_description=\"{}\"", e);
+    let body = Json(serde_json::json!({
+        "status": StatusCode::UNAUTHORIZED.as_u16(),
+        "error": e.to_string(),
+        "reason": reason,
+    }));
+    (
+        StatusCode::UNAUTHORIZED,
+        [(http::header::WWW_AUTHENTICATE, challenge)],
+        body,
+    )
+        .into_response()
+}
+
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct Registration {
     #[schema(example = "First Last")]
-    full_name: String,
+    pub full_name: String,
     #[schema(example = "firstlast@example.com")]
-    email: String,
-    #[schema(example = "openinvite")]
-    access_code: String,
+    pub email: String,
+    /// Invite code from an admin.
+    #[schema(example = "3f9a1c0b7e4d2a6c58b1f0e2")]
+    pub access_code: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Claims {
     #[schema(example = "recipe-server.po8.org")]
     iss: String,
-    #[schema(example = "First Last <firstlast@example.com>")]
-    sub: String,
+    #[schema(example = "recipe-server")]
+    aud: String,
+    /// Id of the user the token was issued to.
+    #[schema(example = "42")]
+    pub sub: String,
     #[schema(example = "1764662400")] // Unix timestamp
-    exp: u64,
+    pub exp: u64,
+    #[schema(example = "1764661500")]
+    nbf: u64,
+    #[schema(example = "1764661500")]
+    iat: u64,
+    #[serde(default)]
+    pub role: Role,
+    /// Unique token id, used to revoke the token at logout.
+    #[serde(default)]
+    pub jti: String,
+    /// Scopes when authenticated by API key; tokens are not limited by scope.
+    #[serde(skip)]
+    pub scopes: Option<Vec<apikeys::Scope>>,
 }
 
-pub fn make_jwt_token(app_state: &AppState, registration: &Registration) -> Result<AuthBody, AuthError> {
-    use jsonwebtoken::{Algorithm, Header, encode};
-    
-    if registration.access_code != app_state.reg_key {
-        return Err(AuthError::Registration);
+impl Claims {
+    /// Claims for a request authenticated by one of a user's API keys.
+    fn api_key(config: &TokenConfig, user_id: i64, role: Role, scopes: Vec<apikeys::Scope>) -> Self {
+        Self {
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            sub: user_id.to_string(),
+            exp: 0,
+            nbf: 0,
+            iat: 0,
+            role,
+            jti: String::new(),
+            scopes: Some(scopes),
+        }
     }
 
-    let iss = "recipe-server.po8.org".to_string();
-    let sub = format!("{} <{}>", registration.full_name, registration.email);
-    let exp = (Utc::now() + TimeDelta::days(1)).timestamp();
-    let exp = u64::try_from(exp).unwrap();
-    let claims = Claims { iss, sub, exp };
-    let header = Header::new(Algorithm::HS512);
-    let token = encode(&header, &claims, &app_state.jwt_keys.encoding)
+    /// Whether the request may act with `scope`.
+    pub fn has_scope(&self, scope: apikeys::Scope) -> bool {
+        self.scopes.as_ref().is_none_or(|scopes| scopes.contains(&scope))
+    }
+}
+
+/// Mint a short-lived access token for a user, carrying their current role.
+pub fn make_jwt_token(app_state: &AppState, user: &users::User) -> Result<AuthBody, AuthError> {
+    let config = &app_state.token_config;
+    let iss = config.issuer.clone();
+    let aud = config.audience.clone();
+    let sub = user.id.to_string();
+    let now = Utc::now();
+    let exp = u64::try_from((now + ACCESS_TTL).timestamp()).unwrap();
+    let iat = u64::try_from(now.timestamp()).unwrap();
+    let nbf = iat;
+    let role = user.role;
+    let jti = tokens::random_hex(16);
+    let scopes = None;
+    let claims = Claims { iss, aud, sub, exp, nbf, iat, role, jti, scopes };
+    let token = app_state
+        .jwt_keys
+        .sign(&claims)
         .map_err(|_| AuthError::TokenCreation)?;
     Ok(AuthBody::new(token))
 }
+
+/// Mint an access token plus a refresh token for a user who just logged in.
+pub async fn issue_tokens(app_state: &AppState, user: &users::User) -> Result<AuthBody, AuthError> {
+    let mut body = make_jwt_token(app_state, user)?;
+    let mut conn = app_state.db.acquire().await.map_err(|_| AuthError::TokenCreation)?;
+    let refresh = tokens::issue(&mut conn, user.id, None)
+        .await
+        .map_err(|_| AuthError::TokenCreation)?;
+    body.refresh_token = Some(refresh);
+    Ok(body)
+}
+
+/// Trade a refresh token for a new access token and the next refresh token.
+///
+/// The role is read afresh, so role changes apply from the next refresh.
+pub async fn refresh_tokens(app_state: &AppState, refresh_token: &str) -> Result<AuthBody, AuthError> {
+    let db = &app_state.db;
+    let (user_id, next) = tokens::rotate(db, refresh_token)
+        .await
+        .map_err(|_| AuthError::TokenCreation)?
+        .ok_or(AuthError::InvalidRefreshToken)?;
+    let user = users::get(db, user_id)
+        .await
+        .map_err(|_| AuthError::InvalidRefreshToken)?;
+    let mut body = make_jwt_token(app_state, &user)?;
+    body.refresh_token = Some(next);
+    Ok(body)
+}
@@ -0,0 +1,426 @@
+//! Cooklang `.cook` files: steps marked up with `@ingredient{qty%unit}`,
+//! `#cookware{}` and `~timer{qty%unit}`, plus `>> key: value` or front matter
+//! metadata.
+//!
+//! Importing collects the ingredients into the ingredient list and turns the
+//! steps into plain instructions. Exporting marks up the first mention of each
+//! ingredient and every "N minutes" in the instructions; ingredients never
+//! mentioned are gathered into a first step.
+
+use crate::*;
+
+use std::collections::HashMap;
+
+/// Words after a number that make it a timer on export.
+const TIME_UNITS: &[&str] = &[
+    "second", "seconds", "sec", "secs", "minute", "minutes", "min", "mins", "hour", "hours",
+    "hr", "hrs",
+];
+
+/// Text with `[- ... -]` block comments and `--` line comments removed.
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[-") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find("-]") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out.lines()
+        .map(|line| line.find("--").map_or(line, |at| &line[..at]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split YAML-style front matter, as `key: value` pairs, from the body.
+/// `- item` lines after a key with no value are joined into a list.
+fn front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = text.trim_start().strip_prefix("---\n") else {
+        return (Vec::new(), text);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (Vec::new(), text);
+    };
+    let body = rest[end + 4..].trim_start_matches('-');
+    let mut metadata: Vec<(String, String)> = Vec::new();
+    for line in rest[..end].lines() {
+        if let Some(item) = line.trim().strip_prefix("- ") {
+            if let Some((_, value)) = metadata.last_mut() {
+                if !value.is_empty() {
+                    value.push_str(", ");
+                }
+                value.push_str(item.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            metadata.push((key.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    (metadata, body)
+}
+
+/// A name after `@`, `#` or `~`: everything up to `{` when that is one plain
+/// phrase, otherwise a single word.
+fn component_name(text: &str) -> (&str, &str) {
+    if let Some(brace) = text.find('{') {
+        let phrase = &text[..brace];
+        if !phrase.contains(['@', '#', '~', '.', ',', ';', '\n']) {
+            return (phrase.trim(), &text[brace..]);
+        }
+    }
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(text.len());
+    (&text[..end], &text[end..])
+}
+
+/// Split a `{qty%unit}` amount off the start of `text`, if it has one.
+fn amount(text: &str) -> (Option<(String, String)>, &str) {
+    let Some(inner) = text.strip_prefix('{') else {
+        return (None, text);
+    };
+    let Some(close) = inner.find('}') else {
+        return (None, text);
+    };
+    let (quantity, unit) = inner[..close].split_once('%').unwrap_or((&inner[..close], ""));
+    let quantity = quantity.trim().trim_start_matches(['=', '*']).trim();
+    (Some((quantity.to_string(), unit.trim().to_string())), &inner[close + 1..])
+}
+
+/// Ingredient line for `@name{quantity%unit}(note)`, such as "1½ cups chickpeas, rinsed".
+fn ingredient_line(name: &str, quantity: &str, unit: &str, note: Option<&str>) -> String {
+    let mut line = [quantity, unit, name]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some(note) = note.filter(|note| !note.is_empty()) {
+        line = format!("{}, {}", line, note);
+    }
+    ingredient::parse(&line).render()
+}
+
+/// Plain text of a step, adding each ingredient it mentions to `ingredients`.
+fn parse_step(step: &str, ingredients: &mut Vec<String>) -> String {
+    let mut plain = String::with_capacity(step.len());
+    let mut rest = step;
+    while let Some(at) = rest.find(['@', '#', '~']) {
+        plain.push_str(&rest[..at]);
+        let sigil = rest[at..].chars().next().unwrap_or('@');
+        let (name, after) = component_name(&rest[at + 1..]);
+        let (amount, after) = amount(after);
+        if name.is_empty() && amount.is_none() {
+            // A lone sigil is just text
+            plain.push(sigil);
+            rest = &rest[at + 1..];
+            continue;
+        }
+        rest = after;
+        match sigil {
+            '@' => {
+                let note = rest
+                    .strip_prefix('(')
+                    .and_then(|r| r.split_once(')'))
+                    .map(|(note, after)| {
+                        rest = after;
+                        note.trim()
+                    });
+                let (quantity, unit) = amount.unwrap_or_default();
+                ingredients.push(ingredient_line(name, &quantity, &unit, note));
+                plain.push_str(name);
+            }
+            '~' => match amount {
+                Some((quantity, unit)) if !quantity.is_empty() => {
+                    plain.push_str(format!("{} {}", quantity, unit).trim());
+                }
+                _ => plain.push_str(name),
+            },
+            _ => plain.push_str(name),
+        }
+    }
+    plain.push_str(rest);
+    plain.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// End the paragraph being read, adding it as a step.
+fn flush(paragraph: &mut Vec<&str>, ingredients: &mut Vec<String>, instructions: &mut Vec<String>) {
+    if !paragraph.is_empty() {
+        let step = parse_step(&paragraph.join(" "), ingredients);
+        if !step.is_empty() {
+            instructions.push(step);
+        }
+        paragraph.clear();
+    }
+}
+
+/// Convert a Cooklang recipe. `fallback_title`, usually the file name, is used
+/// when the metadata has no `title`.
+pub fn parse(text: &str, fallback_title: &str) -> Result<JSONRecipe, CooklangError> {
+    let text = text.replace("\r\n", "\n");
+    let (mut metadata, body) = front_matter(&text);
+    let body = strip_comments(body);
+
+    let mut ingredients = Vec::new();
+    let mut instructions = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in body.lines().map(str::trim) {
+        if let Some(meta) = line.strip_prefix(">>") {
+            if let Some((key, value)) = meta.split_once(':') {
+                metadata.push((key.trim().to_lowercase(), value.trim().to_string()));
+            }
+        } else if line.starts_with('=') {
+            flush(&mut paragraph, &mut ingredients, &mut instructions);
+            let name = line.trim_matches('=').trim();
+            if !name.is_empty() {
+                instructions.push(format!("{}:", name));
+            }
+        } else if line.is_empty() {
+            flush(&mut paragraph, &mut ingredients, &mut instructions);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    flush(&mut paragraph, &mut ingredients, &mut instructions);
+
+    let metadata: HashMap<String, String> = metadata.into_iter().collect();
+    let meta = |keys: &[&str]| {
+        keys.iter()
+            .filter_map(|key| metadata.get(*key))
+            .map(|value| value.trim().trim_matches('"').to_string())
+            .find(|value| !value.is_empty())
+    };
+    let title = meta(&["title"]).unwrap_or_else(|| fallback_title.trim().to_string());
+    let id = recipe::slug(&title);
+    if id.is_empty() {
+        return Err(CooklangError::Untitled);
+    }
+    let tags = meta(&["tags"])
+        .unwrap_or_default()
+        .trim_matches(['[', ']'])
+        .split(',')
+        .map(|tag| tag.trim().trim_matches('"').to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let servings = meta(&["servings", "serves", "yield"]).and_then(|servings| {
+        let digits: String = servings
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(char::is_ascii_digit)
+            .collect();
+        digits.parse().ok().filter(|&n: &i64| n > 0)
+    });
+
+    Ok(JSONRecipe {
+        id,
+        title,
+        ingredients: ingredients.join("\n"),
+        instructions: instructions.join("\n"),
+        source: meta(&["source", "source.url", "url", "author"]).unwrap_or_default(),
+        servings,
+        tags,
+        parsed_ingredients: Vec::new(),
+        created_by: None,
+        created_at: None,
+        updated_at: None,
+    })
+}
+
+/// A quantity as Cooklang writes it, such as `2`, `1.5` or `2-3`.
+fn quantity(ingredient: &Ingredient) -> String {
+    let number = |q: f64| format!("{}", (q * 1000.0).round() / 1000.0);
+    match (ingredient.quantity, ingredient.quantity_max) {
+        (Some(low), Some(high)) => format!("{}-{}", number(low), number(high)),
+        (Some(low), None) => number(low),
+        _ => String::new(),
+    }
+}
+
+/// Markup for an ingredient, such as `@chickpeas{1.5%cup}(rinsed)`.
+fn ingredient_markup(name: &str, ingredient: &Ingredient) -> String {
+    let mut amount = quantity(ingredient);
+    if let Some(unit) = &ingredient.unit {
+        amount = format!("{}%{}", amount, unit);
+    }
+    let mut markup = format!("@{}{{{}}}", name, amount);
+    if let Some(note) = &ingredient.note {
+        markup = format!("{}({})", markup, note);
+    }
+    markup
+}
+
+/// Byte range of the first whole-word, case-insensitive `word` in `text` that
+/// does not overlap `taken`.
+fn find_word(text: &str, word: &str, taken: &[(usize, usize, String)]) -> Option<(usize, usize)> {
+    // ASCII lowercasing keeps byte offsets
+    let lower = text.to_ascii_lowercase();
+    let word = word.to_ascii_lowercase();
+    let boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    lower
+        .match_indices(&word)
+        .map(|(start, _)| (start, start + word.len()))
+        .find(|&(start, end)| {
+            boundary(lower[..start].chars().next_back())
+                && boundary(lower[end..].chars().next())
+                && taken.iter().all(|(s, e, _)| end <= *s || start >= *e)
+        })
+}
+
+/// Spans of "N minutes"-style durations in `text`, as timer markup.
+fn timers(text: &str, taken: &[(usize, usize, String)]) -> Vec<(usize, usize, String)> {
+    let mut found = Vec::new();
+    let words: Vec<(usize, &str)> = text
+        .split_whitespace()
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+        .collect();
+    for pair in words.windows(2) {
+        let [(start, number), (unit_at, unit)] = pair else {
+            continue;
+        };
+        let unit = unit.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let is_number = !number.is_empty()
+            && number.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-' || c == '/')
+            && number.starts_with(|c: char| c.is_ascii_digit());
+        if !is_number || !TIME_UNITS.contains(&unit.to_lowercase().as_str()) {
+            continue;
+        }
+        let end = unit_at + unit.len();
+        if taken.iter().all(|(s, e, _)| end <= *s || *start >= *e) {
+            found.push((*start, end, format!("~{{{}%{}}}", number, unit)));
+        }
+    }
+    found
+}
+
+/// A stored recipe as a Cooklang file.
+pub fn render(recipe: &Recipe, tags: &[String]) -> String {
+    let mut out = format!(">> title: {}\n", recipe.title);
+    if !recipe.recipe_source.trim().is_empty() {
+        out.push_str(&format!(">> source: {}\n", recipe.recipe_source.trim()));
+    }
+    if let Some(servings) = recipe.servings {
+        out.push_str(&format!(">> servings: {}\n", servings));
+    }
+    if !tags.is_empty() {
+        let mut tags = tags.to_vec();
+        tags.sort_unstable();
+        out.push_str(&format!(">> tags: {}\n", tags.join(", ")));
+    }
+
+    let lines: Vec<&str> = recipe
+        .instructions
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    // Spans of each instruction line to replace with markup
+    let mut marks: Vec<Vec<(usize, usize, String)>> = vec![Vec::new(); lines.len()];
+    let mut unmentioned = Vec::new();
+    for ingredient in ingredient::parse_lines(&recipe.ingredients) {
+        let item = ingredient.item.trim();
+        if item.is_empty() || item.contains(['{', '}', '@', '#', '~']) {
+            continue;
+        }
+        // Section lines are written as `== name ==`, which cannot hold markup
+        let found = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.ends_with(':'))
+            .find_map(|(i, line)| find_word(line, item, &marks[i]).map(|span| (i, span)));
+        match found {
+            Some((i, (start, end))) => {
+                let markup = ingredient_markup(&lines[i][start..end], &ingredient);
+                marks[i].push((start, end, markup));
+            }
+            None => unmentioned.push(ingredient_markup(item, &ingredient)),
+        }
+    }
+
+    out.push('\n');
+    if !unmentioned.is_empty() {
+        out.push_str(&format!("Gather {}.\n\n", unmentioned.join(", ")));
+    }
+    for (line, mut marks) in lines.iter().zip(marks) {
+        if let Some(section) = line.strip_suffix(':') {
+            out.push_str(&format!("== {} ==\n\n", section));
+            continue;
+        }
+        let timers = timers(line, &marks);
+        marks.extend(timers);
+        marks.sort_by_key(|(start, _, _)| *start);
+        let mut at = 0;
+        for (start, end, markup) in marks {
+            out.push_str(&line[at..start]);
+            out.push_str(&markup);
+            at = end;
+        }
+        out.push_str(&line[at..]);
+        out.push_str("\n\n");
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// Add the recipe in a `.cook` file, titled after the file when its metadata
+/// has no title, with `author` on its first revision and no owner. Returns the
+/// id it was given.
+pub async fn import_file(
+    db: &SqlitePool,
+    path: &std::path::Path,
+    author: &str,
+) -> Result<String, CooklangError> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    let recipe = parse(&text, stem)?;
+    Ok(recipe::add_unique(db, recipe, author).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A recipe exported as Cooklang and read back in.
+    fn round_trip(ingredients: &str, instructions: &str) -> JSONRecipe {
+        let recipe: JSONRecipe = serde_json::from_value(serde_json::json!({
+            "id": "dressing",
+            "title": "Dressing",
+            "ingredients": ingredients,
+            "instructions": instructions,
+            "source": "",
+            "tags": [],
+        }))
+        .expect("valid recipe");
+        let (recipe, _) = recipe.to_recipe();
+        parse(&render(&recipe, &[]), "").expect("exported recipe parses")
+    }
+
+    #[test]
+    fn mentioned_ingredients_round_trip() {
+        let back = round_trip(
+            "2 tbsp olive oil\n1 tsp salt",
+            "Whisk the olive oil and salt.\nRest 5 minutes.\nServe.",
+        );
+        assert_eq!(back.ingredients, "2 tbsp olive oil\n1 tsp salt");
+        assert_eq!(back.instructions, "Whisk the olive oil and salt.\nRest 5 minutes.\nServe.");
+    }
+
+    #[test]
+    fn ingredients_on_section_lines_round_trip() {
+        let back = round_trip("2 tbsp olive oil\n1 tsp salt", "Whisk the olive oil and salt:\nServe.");
+        assert_eq!(back.ingredients, "2 tbsp olive oil\n1 tsp salt");
+        assert_eq!(
+            back.instructions,
+            "Gather olive oil, salt.\nWhisk the olive oil and salt:\nServe."
+        );
+    }
+
+    #[test]
+    fn unmentioned_ingredients_are_gathered() {
+        let back = round_trip("1 lemon\n2 tbsp olive oil", "Dress the leaves with olive oil.");
+        assert_eq!(back.ingredients, "1 lemon\n2 tbsp olive oil");
+        assert_eq!(back.instructions, "Gather lemon.\nDress the leaves with olive oil.");
+    }
+}
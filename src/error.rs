@@ -7,4 +7,86 @@ pub enum RecipeError {
     RecipesNotFound(#[from] std::io::Error),
     #[error("could not read recipes file: {0}")]
     RecipesMisformat(#[from] serde_json::Error),
+    #[error("invalid scale: {0}")]
+    InvalidScale(&'static str),
+    #[error("search needs at least one word")]
+    EmptySearch,
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("invalid tag query: {0}")]
+    TagQuery(#[from] TagQueryError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum TagQueryError {
+    #[error("empty tag query")]
+    Empty,
+    #[error("empty tag at position {0}")]
+    EmptyTag(usize),
+    #[error("unclosed quote at position {0}")]
+    UnclosedQuote(usize),
+    #[error("unexpected {found} at position {position}")]
+    Unexpected { found: String, position: usize },
+    #[error("unexpected end of tag query")]
+    UnexpectedEnd,
+    #[error("tag query is nested too deeply")]
+    TooDeep,
+    #[error("tag query names more than {0} tags")]
+    TooManyTerms(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum UserError {
+    #[error("invalid email address")]
+    InvalidEmail,
+    #[error("display name is required")]
+    MissingName,
+    #[error("password must be at least {0} characters")]
+    WeakPassword(usize),
+    #[error("email is already registered")]
+    EmailTaken,
+    #[error("no such user")]
+    NotFound,
+    #[error("invalid API key request: {0}")]
+    InvalidApiKey(&'static str),
+    #[error("invalid invite request: {0}")]
+    InvalidInvite(&'static str),
+    #[error("invite code is unknown, expired, revoked or used up")]
+    InviteRejected,
+    #[error("could not hash password")]
+    Hashing,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("invalid {0} time, expected RFC 3339 such as 2025-06-01T12:00:00Z")]
+    InvalidTime(&'static str),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum JsonLdError {
+    #[error("could not read page: {0}")]
+    Unreadable(#[from] std::io::Error),
+    #[error("page has no schema.org Recipe in its JSON-LD")]
+    NoRecipe,
+    #[error("recipe has no name")]
+    Unnamed,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum CooklangError {
+    #[error("could not read recipe: {0}")]
+    Unreadable(#[from] std::io::Error),
+    #[error("recipe has no title")]
+    Untitled,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
 }
@@ -0,0 +1,128 @@
+//! Export of every recipe in the format `--init-from` reads.
+
+use crate::*;
+
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+
+/// Recipes sent ahead of a slow reader before the export waits for it.
+const BUFFERED: usize = 32;
+
+/// Layout of an export.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One JSON array, as in `assets/static/recipes.json`.
+    #[default]
+    Json,
+    /// One recipe per line.
+    Ndjson,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// Query parameters for exporting recipes.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct ExportParams {
+    /// `json` for an array (the default) or `ndjson` for one recipe per line.
+    pub format: Option<Format>,
+}
+
+/// Every recipe with its tags, in id order, read from the database as it goes.
+fn recipes(db: &SqlitePool) -> impl Stream<Item = Result<JSONRecipe, sqlx::Error>> + '_ {
+    sqlx::query!(
+        r#"SELECT id, title, ingredients, instructions, recipe_source, servings, version,
+            updated_at, created_at, created_by,
+            (SELECT json_group_array(tag) FROM tags WHERE recipe_id = recipes.id) AS "tags!: String",
+            (SELECT json_group_array(json_object(
+                'raw', raw, 'quantity', quantity, 'quantity_max', quantity_max,
+                'unit', unit, 'item', item, 'note', note))
+            FROM (SELECT * FROM ingredients WHERE recipe_id = recipes.id ORDER BY position))
+                AS "parsed_ingredients!: String"
+        FROM recipes ORDER BY id;"#,
+    )
+    .fetch(db)
+    .map(|row| {
+        let row = row?;
+        let tags: Vec<String> = serde_json::from_str(&row.tags).unwrap_or_default();
+        let parsed = serde_json::from_str(&row.parsed_ingredients).unwrap_or_default();
+        let recipe = Recipe {
+            id: row.id,
+            title: row.title,
+            ingredients: row.ingredients,
+            instructions: row.instructions,
+            recipe_source: row.recipe_source,
+            servings: row.servings,
+            version: row.version,
+            updated_at: row.updated_at,
+            created_at: row.created_at,
+            created_by: row.created_by,
+        };
+        Ok(JSONRecipe::new(recipe, tags, parsed))
+    })
+}
+
+/// The export as chunks of text, produced by a task that reads one recipe at a
+/// time so the whole collection is never held in memory.
+pub fn chunks(db: SqlitePool, format: Format) -> ReceiverStream<Result<String, sqlx::Error>> {
+    let (tx, rx) = mpsc::channel(BUFFERED);
+    tokio::spawn(async move {
+        let recipes = recipes(&db);
+        tokio::pin!(recipes);
+        let mut first = true;
+        while let Some(recipe) = recipes.next().await {
+            let recipe = match recipe {
+                Ok(recipe) => recipe,
+                Err(e) => {
+                    log::error!("export: reading recipes: {}", e);
+                    let _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
+            let json = serde_json::to_string(&recipe).expect("recipe serializes");
+            let chunk = match (format, first) {
+                (Format::Json, true) => format!("[\n{}", json),
+                (Format::Json, false) => format!(",\n{}", json),
+                (Format::Ndjson, _) => format!("{}\n", json),
+            };
+            first = false;
+            // The reader went away
+            if tx.send(Ok(chunk)).await.is_err() {
+                return;
+            }
+        }
+        if format == Format::Json {
+            let end = if first { "[]\n" } else { "\n]\n" };
+            let _ = tx.send(Ok(end.to_string())).await;
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Write the export to a file, or to stdout for `-`.
+pub async fn write(
+    db: &SqlitePool,
+    path: &std::path::Path,
+    format: Format,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut out: Box<dyn tokio::io::AsyncWrite + Unpin> = if path == std::path::Path::new("-") {
+        Box::new(tokio::io::stdout())
+    } else {
+        Box::new(tokio::fs::File::create(path).await?)
+    };
+    let mut chunks = chunks(db.clone(), format);
+    while let Some(chunk) = chunks.next().await {
+        out.write_all(chunk?.as_bytes()).await?;
+    }
+    out.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! Bulk import of recipes in the format `--export` writes, shared by
+//! `--init-from` and `POST /api/v1/import`.
+//!
+//! Each recipe is written in its own transaction, so one bad recipe fails
+//! alone. A dry run does all the same work and rolls every transaction back.
+
+use crate::*;
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// What to do with recipes that are already in the database.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Add new recipes; an existing recipe that differs fails.
+    #[default]
+    Insert,
+    /// Add new recipes and overwrite existing ones that differ.
+    Upsert,
+    /// Upsert, then delete every recipe the import does not contain.
+    Replace,
+}
+
+impl Mode {
+    /// Lowest role allowed to import in this mode over the API.
+    pub fn min_role(self) -> Role {
+        match self {
+            Mode::Insert => Role::Contributor,
+            Mode::Upsert => Role::Editor,
+            Mode::Replace => Role::Admin,
+        }
+    }
+}
+
+/// Query parameters for importing recipes.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct ImportParams {
+    /// `insert` (the default), `upsert` or `replace`.
+    pub mode: Option<Mode>,
+    /// Report what would change without changing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What happened, or would happen, to one recipe.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    New,
+    Updated,
+    Unchanged,
+    /// Left out of a `replace` import.
+    Deleted,
+    Failed,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::New => write!(f, "new"),
+            Outcome::Updated => write!(f, "updated"),
+            Outcome::Unchanged => write!(f, "unchanged"),
+            Outcome::Deleted => write!(f, "deleted"),
+            Outcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// One recipe's line in the report.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecipeReport {
+    #[schema(example = "pancakes")]
+    pub id: String,
+    pub outcome: Outcome,
+    /// Why the recipe failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Recipes per outcome.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct Summary {
+    pub new: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+    pub failed: usize,
+}
+
+/// Result of an import, one line per recipe in the order they were handled.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportReport {
+    pub mode: Mode,
+    pub dry_run: bool,
+    pub summary: Summary,
+    pub recipes: Vec<RecipeReport>,
+}
+
+impl ImportReport {
+    fn push(&mut self, id: &str, outcome: Outcome, reason: Option<String>) {
+        let count = match outcome {
+            Outcome::New => &mut self.summary.new,
+            Outcome::Updated => &mut self.summary.updated,
+            Outcome::Unchanged => &mut self.summary.unchanged,
+            Outcome::Deleted => &mut self.summary.deleted,
+            Outcome::Failed => &mut self.summary.failed,
+        };
+        *count += 1;
+        self.recipes.push(RecipeReport {
+            id: id.to_string(),
+            outcome,
+            reason,
+        });
+    }
+}
+
+/// Who an import runs as.
+pub struct Importer<'a> {
+    /// Author of the revisions it writes.
+    pub author: &'a str,
+    /// Owner of the recipes it adds; none for seeded recipes.
+    pub owner: Option<&'a str>,
+    /// Request to record each recipe added, updated or deleted under in the
+    /// audit log, with `author` as the actor; none to leave them unrecorded.
+    pub request_id: Option<&'a audit::RequestId>,
+}
+
+/// A stored recipe as the audit log records it.
+async fn snapshot(conn: &mut sqlx::SqliteConnection, id: &str) -> Result<Value, sqlx::Error> {
+    let recipe = sqlx::query_as!(Recipe, "SELECT * FROM recipes WHERE id = $1;", id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let tags = sqlx::query_scalar!("SELECT tag FROM tags WHERE recipe_id = $1;", id)
+        .fetch_all(&mut *conn)
+        .await?;
+    let ingredients = ingredient::load(&mut *conn, id).await?;
+    Ok(serde_json::json!(JSONRecipe::new(recipe, tags, ingredients)))
+}
+
+/// Record a committed change in the audit log, if the import is audited.
+async fn audit(
+    db: &SqlitePool,
+    importer: &Importer<'_>,
+    id: &str,
+    outcome: Outcome,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    let Some(request_id) = importer.request_id else {
+        return;
+    };
+    let action = match outcome {
+        Outcome::New => "recipe.add",
+        Outcome::Updated => "recipe.update",
+        Outcome::Deleted => "recipe.delete",
+        Outcome::Unchanged | Outcome::Failed => return,
+    };
+    let mut event = audit::Event::new(action)
+        .actor(importer.author)
+        .target(id)
+        .before(before);
+    if let Some(after) = after {
+        event = event.after(after);
+    }
+    audit::record(db, request_id, event).await;
+}
+
+/// Reason to refuse a recipe before touching the database.
+fn invalid(recipe: &JSONRecipe, seen: &HashSet<String>) -> Option<String> {
+    if recipe.id.trim().is_empty() {
+        Some("id is empty".to_string())
+    } else if recipe.title.trim().is_empty() {
+        Some("title is empty".to_string())
+    } else if seen.contains(&recipe.id) {
+        Some("id appears more than once in the import".to_string())
+    } else {
+        None
+    }
+}
+
+/// Import one recipe inside `conn`'s transaction, returning what happened and
+/// the recipe as it was before an update.
+async fn import_one(
+    conn: &mut sqlx::SqliteConnection,
+    recipe: &JSONRecipe,
+    mode: Mode,
+    importer: &Importer<'_>,
+) -> Result<(Outcome, Option<Value>), String> {
+    let (mut new, _) = recipe.to_recipe();
+    new.created_by = importer.owner.map(str::to_string);
+
+    let existing = sqlx::query_as!(Recipe, "SELECT * FROM recipes WHERE id = $1;", recipe.id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(existing) = existing else {
+        recipe::insert(conn, &new, &recipe.tags, importer.author)
+            .await
+            .map_err(|e| e.to_string())?;
+        return Ok((Outcome::New, None));
+    };
+
+    let tags: HashSet<String> =
+        sqlx::query_scalar!("SELECT tag FROM tags WHERE recipe_id = $1;", recipe.id)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .collect();
+    let same = existing.title == new.title
+        && existing.ingredients == new.ingredients
+        && existing.instructions == new.instructions
+        && existing.recipe_source == new.recipe_source
+        && existing.servings == new.servings
+        && tags == recipe.tags;
+    if same {
+        return Ok((Outcome::Unchanged, None));
+    }
+    if mode == Mode::Insert {
+        return Err("already exists and differs; use upsert or replace".to_string());
+    }
+    let before = snapshot(conn, &recipe.id).await.map_err(|e| e.to_string())?;
+    recipe::write(conn, &new, &recipe.tags, existing.version, importer.author)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((Outcome::Updated, Some(before)))
+}
+
+/// Import `recipes` in `mode`, or only report what would change on a dry run.
+///
+/// Only failing to reach the database fails the whole import; a recipe that
+/// cannot be imported is reported as failed and the rest carry on.
+pub async fn run(
+    db: &SqlitePool,
+    recipes: &[JSONRecipe],
+    mode: Mode,
+    dry_run: bool,
+    importer: &Importer<'_>,
+) -> Result<ImportReport, sqlx::Error> {
+    let mut report = ImportReport {
+        mode,
+        dry_run,
+        summary: Summary::default(),
+        recipes: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    for recipe in recipes {
+        if let Some(reason) = invalid(recipe, &seen) {
+            report.push(&recipe.id, Outcome::Failed, Some(reason));
+            continue;
+        }
+        seen.insert(recipe.id.clone());
+
+        let mut tx = db.begin().await?;
+        match import_one(&mut tx, recipe, mode, importer).await {
+            Ok((outcome, before)) => {
+                if dry_run {
+                    tx.rollback().await?;
+                } else {
+                    let after = match outcome {
+                        Outcome::New | Outcome::Updated => snapshot(&mut tx, &recipe.id).await.ok(),
+                        _ => None,
+                    };
+                    tx.commit().await?;
+                    audit(db, importer, &recipe.id, outcome, before, after).await;
+                }
+                report.push(&recipe.id, outcome, None);
+            }
+            Err(reason) => {
+                tx.rollback().await?;
+                report.push(&recipe.id, Outcome::Failed, Some(reason));
+            }
+        }
+    }
+
+    if mode == Mode::Replace {
+        // Recipes that failed are still in the import, so they are kept
+        let listed: HashSet<&str> = recipes.iter().map(|recipe| recipe.id.as_str()).collect();
+        let stored = sqlx::query!("SELECT id, version FROM recipes ORDER BY id;")
+            .fetch_all(db)
+            .await?;
+        for row in stored.into_iter().filter(|row| !listed.contains(row.id.as_str())) {
+            let mut tx = db.begin().await?;
+            let before = snapshot(&mut tx, &row.id).await.ok();
+            match recipe::remove(&mut tx, &row.id, row.version).await {
+                Ok(()) => {
+                    if dry_run {
+                        tx.rollback().await?;
+                    } else {
+                        tx.commit().await?;
+                        audit(db, importer, &row.id, Outcome::Deleted, before, None).await;
+                    }
+                    report.push(&row.id, Outcome::Deleted, None);
+                }
+                Err(e) => {
+                    tx.rollback().await?;
+                    report.push(&row.id, Outcome::Failed, Some(e.to_string()));
+                }
+            }
+        }
+    }
+    Ok(report)
+}
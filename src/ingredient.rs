@@ -0,0 +1,409 @@
+use crate::*;
+
+/// One line of a recipe's ingredient list broken into its parts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Ingredient {
+    /// The line exactly as written in the recipe.
+    #[schema(example = "1½ cups chickpeas")]
+    pub raw: String,
+    #[schema(example = 1.5)]
+    pub quantity: Option<f64>,
+    /// Upper bound when the quantity is a range such as "2 to 4".
+    pub quantity_max: Option<f64>,
+    #[schema(example = "cup")]
+    pub unit: Option<String>,
+    #[schema(example = "chickpeas")]
+    pub item: String,
+    pub note: Option<String>,
+}
+
+/// Canonical unit names and the spellings that map to them.
+const UNITS: &[(&str, &[&str])] = &[
+    ("tsp", &["tsp", "tsps", "teaspoon", "teaspoons"]),
+    ("tbsp", &["tbsp", "tbsps", "tbs", "tablespoon", "tablespoons"]),
+    ("cup", &["cup", "cups", "c"]),
+    ("fl oz", &["fl oz", "fluid ounce", "fluid ounces"]),
+    ("pint", &["pint", "pints", "pt"]),
+    ("quart", &["quart", "quarts", "qt"]),
+    ("gallon", &["gallon", "gallons", "gal"]),
+    ("ml", &["ml", "milliliter", "milliliters", "millilitre", "millilitres"]),
+    ("l", &["l", "liter", "liters", "litre", "litres"]),
+    ("oz", &["oz", "ounce", "ounces"]),
+    ("lb", &["lb", "lbs", "pound", "pounds"]),
+    ("g", &["g", "gram", "grams"]),
+    ("kg", &["kg", "kilogram", "kilograms"]),
+    ("clove", &["clove", "cloves"]),
+    ("can", &["can", "cans"]),
+    ("pinch", &["pinch", "pinches"]),
+    ("dash", &["dash", "dashes"]),
+    ("handful", &["handful", "handfuls"]),
+    ("head", &["head", "heads"]),
+    ("slice", &["slice", "slices"]),
+    ("stick", &["stick", "sticks"]),
+    ("package", &["package", "packages", "pkg"]),
+    ("bunch", &["bunch", "bunches"]),
+    ("sprig", &["sprig", "sprigs"]),
+];
+
+/// Value of a single unicode vulgar fraction character.
+fn unicode_fraction(c: char) -> Option<f64> {
+    let value = match c {
+        '½' => 1.0 / 2.0,
+        '⅓' => 1.0 / 3.0,
+        '⅔' => 2.0 / 3.0,
+        '¼' => 1.0 / 4.0,
+        '¾' => 3.0 / 4.0,
+        '⅕' => 1.0 / 5.0,
+        '⅖' => 2.0 / 5.0,
+        '⅗' => 3.0 / 5.0,
+        '⅘' => 4.0 / 5.0,
+        '⅙' => 1.0 / 6.0,
+        '⅚' => 5.0 / 6.0,
+        '⅛' => 1.0 / 8.0,
+        '⅜' => 3.0 / 8.0,
+        '⅝' => 5.0 / 8.0,
+        '⅞' => 7.0 / 8.0,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Split off a leading run of ASCII digits and decimal points.
+fn split_decimal(s: &str) -> Option<(f64, &str)> {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let value = s[..end].parse().ok()?;
+    Some((value, &s[end..]))
+}
+
+/// Split off a simple fraction such as "1/2" or a unicode fraction such as "½".
+fn split_fraction(s: &str) -> Option<(f64, &str)> {
+    let mut chars = s.chars();
+    if let Some(value) = chars.next().and_then(unicode_fraction) {
+        return Some((value, chars.as_str()));
+    }
+    let (numerator, rest) = split_decimal(s)?;
+    let rest = rest.strip_prefix(['/', '⁄'])?;
+    let (denominator, rest) = split_decimal(rest)?;
+    if denominator == 0.0 {
+        return None;
+    }
+    Some((numerator / denominator, rest))
+}
+
+/// Split off a number, including mixed numbers like "1½" and "1 1/2".
+fn split_number(s: &str) -> Option<(f64, &str)> {
+    if let Some(split) = split_fraction(s) {
+        return Some(split);
+    }
+    let (whole, rest) = split_decimal(s)?;
+    if let Some((fraction, rest)) = split_fraction(rest.trim_start()) {
+        return Some((whole + fraction, rest));
+    }
+    Some((whole, rest))
+}
+
+/// Split off a quantity or a quantity range such as "2-3" or "2 to 4".
+fn split_amount(s: &str) -> Option<(f64, Option<f64>, &str)> {
+    let (low, rest) = split_number(s)?;
+    let after = rest.trim_start();
+    let upper = after
+        .strip_prefix(['-', '–'])
+        .or_else(|| after.strip_prefix("to "))
+        .and_then(|r| split_number(r.trim_start()));
+    match upper {
+        Some((high, rest)) => Some((low, Some(high), rest)),
+        None => Some((low, None, rest)),
+    }
+}
+
+/// Look up the canonical name of a unit spelling.
+pub fn canonical_unit(word: &str) -> Option<&'static str> {
+    let word = word.trim_end_matches('.').to_lowercase();
+    UNITS
+        .iter()
+        .find(|(_, aliases)| aliases.contains(&word.as_str()))
+        .map(|(name, _)| *name)
+}
+
+/// Split off a leading unit word, trying two-word units first.
+fn split_unit(s: &str) -> (Option<String>, &str) {
+    let s = s.trim_start();
+    let word_end = |s: &str| {
+        s.find(|c: char| !(c.is_alphabetic() || c == '.'))
+            .unwrap_or(s.len())
+    };
+    let first = word_end(s);
+    if first == 0 {
+        return (None, s);
+    }
+    let after_first = &s[first..];
+    if let Some(second_start) = after_first.strip_prefix(' ') {
+        let second = word_end(second_start);
+        let two_words = &s[..first + 1 + second];
+        if second > 0
+            && let Some(unit) = canonical_unit(two_words)
+        {
+            return (Some(unit.to_string()), &second_start[second..]);
+        }
+    }
+    match canonical_unit(&s[..first]) {
+        Some(unit) => (Some(unit.to_string()), after_first),
+        None => (None, s),
+    }
+}
+
+/// Parse a single ingredient line such as "1 clove garlic, minced".
+pub fn parse(line: &str) -> Ingredient {
+    let raw = line.trim();
+    let (quantity, quantity_max, rest) = match split_amount(raw) {
+        Some((low, high, rest)) => (Some(low), high, rest),
+        None => (None, None, raw),
+    };
+    let (unit, rest) = match quantity {
+        Some(_) => split_unit(rest),
+        None => (None, rest),
+    };
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix("of ").unwrap_or(rest);
+
+    let (item, mut notes) = match rest.split_once(',') {
+        Some((item, note)) => (item.trim(), vec![note.trim()]),
+        None => (rest.trim(), vec![]),
+    };
+    let item = match item.strip_suffix(')').and_then(|i| i.split_once('(')) {
+        Some((item, paren)) => {
+            notes.insert(0, paren.trim());
+            item.trim()
+        }
+        None => item,
+    };
+    let note = notes.join(", ");
+
+    Ingredient {
+        raw: raw.to_string(),
+        quantity,
+        quantity_max,
+        unit,
+        item: item.to_string(),
+        note: (!note.is_empty()).then_some(note),
+    }
+}
+
+/// Units written as whole words that take a plural form.
+const COUNTED_UNITS: &[(&str, &str)] = &[
+    ("cup", "cups"),
+    ("pint", "pints"),
+    ("quart", "quarts"),
+    ("gallon", "gallons"),
+    ("clove", "cloves"),
+    ("can", "cans"),
+    ("pinch", "pinches"),
+    ("dash", "dashes"),
+    ("handful", "handfuls"),
+    ("head", "heads"),
+    ("slice", "slices"),
+    ("stick", "sticks"),
+    ("package", "packages"),
+    ("bunch", "bunches"),
+    ("sprig", "sprigs"),
+];
+
+/// Metric units shown as decimals rather than kitchen fractions.
+const DECIMAL_UNITS: &[&str] = &["g", "kg", "ml", "l"];
+
+/// Kitchen fractions a quantity is rounded to, with their unicode glyphs.
+const FRACTIONS: &[(f64, &str)] = &[
+    (0.0, ""),
+    (1.0 / 8.0, "⅛"),
+    (1.0 / 4.0, "¼"),
+    (1.0 / 3.0, "⅓"),
+    (3.0 / 8.0, "⅜"),
+    (1.0 / 2.0, "½"),
+    (5.0 / 8.0, "⅝"),
+    (2.0 / 3.0, "⅔"),
+    (3.0 / 4.0, "¾"),
+    (7.0 / 8.0, "⅞"),
+    (1.0, ""),
+];
+
+/// Format a quantity as a whole number plus the nearest kitchen fraction.
+pub fn format_fraction(quantity: f64) -> String {
+    if quantity >= 10.0 {
+        return format!("{}", quantity.round());
+    }
+    let whole = quantity.trunc();
+    let (fraction, glyph) = FRACTIONS
+        .iter()
+        .min_by(|(a, _), (b, _)| {
+            let da = (quantity - whole - a).abs();
+            let db = (quantity - whole - b).abs();
+            da.total_cmp(&db)
+        })
+        .copied()
+        .unwrap_or((0.0, ""));
+    let whole = whole + fraction.trunc();
+    match (whole == 0.0, glyph.is_empty()) {
+        (true, true) if quantity > 0.0 => "⅛".to_string(),
+        (true, true) => "0".to_string(),
+        (true, false) => glyph.to_string(),
+        (false, _) => format!("{}{}", whole, glyph),
+    }
+}
+
+/// Format a quantity as a rounded decimal for metric units.
+///
+/// Large units (kg, l) keep one decimal place; small ones round to half units.
+pub fn format_decimal(quantity: f64, unit: &str) -> String {
+    if quantity >= 100.0 {
+        format!("{}", (quantity / 5.0).round() * 5.0)
+    } else if quantity >= 10.0 {
+        format!("{}", quantity.round())
+    } else if matches!(unit, "kg" | "l") {
+        format!("{}", (quantity * 10.0).round().max(1.0) / 10.0)
+    } else {
+        format!("{}", (quantity * 2.0).round().max(1.0) / 2.0)
+    }
+}
+
+impl Ingredient {
+    /// Text following the quantity and unit in the original line.
+    fn remainder(&self) -> &str {
+        match split_amount(&self.raw) {
+            Some((_, _, rest)) => split_unit(rest).1,
+            None => &self.raw,
+        }
+    }
+
+    /// Re-render the line from its quantity and unit, keeping the rest of the original text.
+    pub fn render(&self) -> String {
+        let Some(quantity) = self.quantity else {
+            return self.raw.clone();
+        };
+        let format = |q| match self.unit.as_deref() {
+            Some(unit) if DECIMAL_UNITS.contains(&unit) => format_decimal(q, unit),
+            _ => format_fraction(q),
+        };
+        let mut line = format(quantity);
+        if let Some(max) = self.quantity_max {
+            line = format!("{} to {}", line, format(max));
+        }
+        if let Some(unit) = &self.unit {
+            let plural = self.quantity_max.unwrap_or(quantity) > 1.0;
+            let unit = COUNTED_UNITS
+                .iter()
+                .find(|(singular, _)| singular == unit)
+                .map(|(singular, many)| if plural { *many } else { *singular })
+                .unwrap_or(unit.as_str());
+            line = format!("{} {}", line, unit);
+        }
+        let rest = self.remainder();
+        match rest.starts_with(char::is_whitespace) {
+            true => format!("{}{}", line, rest),
+            false => format!("{} {}", line, rest),
+        }
+    }
+
+    /// Multiply the quantity by `factor` and re-render the line.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let mut scaled = self.clone();
+        scaled.quantity = self.quantity.map(|q| q * factor);
+        scaled.quantity_max = self.quantity_max.map(|q| q * factor);
+        scaled.raw = scaled.render();
+        scaled
+    }
+}
+
+/// Scale every quantity in a newline separated ingredient list.
+pub fn scale_lines(ingredients: &str, factor: f64) -> String {
+    scale_all(&parse_lines(ingredients), factor)
+        .into_iter()
+        .map(|i| i.raw)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scale every quantity in a recipe's parsed ingredients.
+pub fn scale_all(ingredients: &[Ingredient], factor: f64) -> Vec<Ingredient> {
+    ingredients.iter().map(|i| i.scaled(factor)).collect()
+}
+
+/// Parse every non-blank line of a newline separated ingredient list.
+pub fn parse_lines(ingredients: &str) -> Vec<Ingredient> {
+    ingredients
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse)
+        .collect()
+}
+
+/// Replace the parsed ingredient rows stored for a recipe.
+pub async fn store(
+    conn: &mut sqlx::SqliteConnection,
+    recipe_id: &str,
+    ingredients: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM ingredients WHERE recipe_id = $1;", recipe_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for (position, ingredient) in parse_lines(ingredients).into_iter().enumerate() {
+        let position = position as i64;
+        sqlx::query!(
+            r#"INSERT INTO ingredients
+            (recipe_id, position, raw, quantity, quantity_max, unit, item, note)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8);"#,
+            recipe_id,
+            position,
+            ingredient.raw,
+            ingredient.quantity,
+            ingredient.quantity_max,
+            ingredient.unit,
+            ingredient.item,
+            ingredient.note,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The parsed ingredient rows stored for a recipe, in list order.
+pub async fn load<'e>(
+    db: impl sqlx::SqliteExecutor<'e>,
+    recipe_id: &str,
+) -> Result<Vec<Ingredient>, sqlx::Error> {
+    sqlx::query_as!(
+        Ingredient,
+        r#"SELECT raw, quantity, quantity_max, unit, item, note FROM ingredients
+        WHERE recipe_id = $1 ORDER BY position;"#,
+        recipe_id,
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Parse and store the ingredients of the recipes the `ingredient_backfill`
+/// migration queued, emptying the queue. Once it is empty this does nothing.
+pub async fn backfill(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let pending = sqlx::query!(
+        r#"SELECT recipes.id, recipes.ingredients FROM ingredient_backfill
+        JOIN recipes ON recipes.id = ingredient_backfill.recipe_id;"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    for recipe in pending {
+        let mut tx = db.begin().await?;
+        store(&mut tx, &recipe.id, &recipe.ingredients).await?;
+        sqlx::query!("DELETE FROM ingredient_backfill WHERE recipe_id = $1;", recipe.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        log::info!("ingredient backfill: {}", recipe.id);
+    }
+    // Recipes deleted while queued
+    sqlx::query!("DELETE FROM ingredient_backfill;").execute(db).await?;
+    Ok(())
+}
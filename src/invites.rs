@@ -0,0 +1,193 @@
+use crate::*;
+
+/// Days an invite lasts when no lifetime is asked for.
+const DEFAULT_TTL_DAYS: i64 = 7;
+
+/// Request to mint an invite code.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct NewInvite {
+    /// How many accounts the code can register; one when left out.
+    pub max_uses: Option<i64>,
+    /// Days until the code stops working; seven when left out.
+    pub expires_in_days: Option<i64>,
+    #[schema(example = "cooking club")]
+    pub note: Option<String>,
+    /// Reserve the code for this email. Such an invite is single-use, and can
+    /// also set a password on the email's passwordless account.
+    #[schema(example = "firstlast@example.com")]
+    pub email: Option<String>,
+}
+
+/// An invite as listed; the code itself is only shown when minted.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Invite {
+    pub id: i64,
+    pub note: Option<String>,
+    /// Email the code is reserved for.
+    pub email: Option<String>,
+    pub max_uses: i64,
+    pub uses: i64,
+    pub expires_at: Option<String>,
+    /// Admin who minted it; missing for invites made from the command line.
+    pub created_by: Option<i64>,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+    /// Ids of the users who registered with it.
+    pub used_by: Vec<i64>,
+}
+
+/// A freshly minted invite, including the code.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedInvite {
+    /// Send as `access_code` to register or sign up. It cannot be shown again.
+    #[schema(example = "3f9a1c0b7e4d2a6c58b1f0e2")]
+    pub code: String,
+    #[serde(flatten)]
+    pub info: Invite,
+}
+
+/// A code as found by [`find`]: the invite's id and the email it is reserved for.
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub id: i64,
+    pub email: Option<String>,
+}
+
+/// Mint an invite code, on behalf of an admin or of the command line.
+pub async fn create(
+    db: &SqlitePool,
+    created_by: Option<i64>,
+    request: NewInvite,
+) -> Result<CreatedInvite, UserError> {
+    let max_uses = request.max_uses.unwrap_or(1);
+    if max_uses <= 0 {
+        return Err(UserError::InvalidInvite("max_uses must be positive"));
+    }
+    let email = request.email.as_deref().map(users::normalize_email).transpose()?;
+    if email.is_some() && max_uses != 1 {
+        return Err(UserError::InvalidInvite("an invite reserved for an email is single-use"));
+    }
+    let days = request.expires_in_days.unwrap_or(DEFAULT_TTL_DAYS);
+    if days <= 0 {
+        return Err(UserError::InvalidInvite("expires_in_days must be positive"));
+    }
+    let expires_at = TimeDelta::try_days(days)
+        .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+        .ok_or(UserError::InvalidInvite("expires_in_days is too large"))?
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string();
+    let note = request
+        .note
+        .map(|note| note.trim().to_string())
+        .filter(|note| !note.is_empty());
+
+    let code = tokens::random_hex(12);
+    let code_hash = tokens::hash(&code);
+    let row = sqlx::query!(
+        r#"INSERT INTO invites (code_hash, note, email, max_uses, expires_at, created_by, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        RETURNING id AS "id!", created_at;"#,
+        code_hash,
+        note,
+        email,
+        max_uses,
+        expires_at,
+        created_by,
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(CreatedInvite {
+        code,
+        info: Invite {
+            id: row.id,
+            note,
+            email,
+            max_uses,
+            uses: 0,
+            expires_at: Some(expires_at),
+            created_by,
+            created_at: row.created_at,
+            revoked_at: None,
+            used_by: Vec::new(),
+        },
+    })
+}
+
+/// Every invite with the users it registered, newest first.
+pub async fn list(db: &SqlitePool) -> Result<Vec<Invite>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id AS "id!", note, email, max_uses, uses, expires_at, created_by, created_at, revoked_at
+        FROM invites ORDER BY id DESC;"#,
+    )
+    .fetch_all(db)
+    .await?;
+    let registrations = sqlx::query!(
+        r#"SELECT id AS "id!", invite_id AS "invite_id!" FROM users
+        WHERE invite_id IS NOT NULL ORDER BY id;"#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Invite {
+            id: row.id,
+            note: row.note,
+            email: row.email,
+            max_uses: row.max_uses,
+            uses: row.uses,
+            expires_at: row.expires_at,
+            created_by: row.created_by,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at,
+            used_by: registrations
+                .iter()
+                .filter(|user| user.invite_id == row.id)
+                .map(|user| user.id)
+                .collect(),
+        })
+        .collect())
+}
+
+/// Revoke an invite so it registers no one else. Returns `RowNotFound` when
+/// there is no such live invite.
+pub async fn revoke(db: &SqlitePool, invite_id: i64) -> Result<(), sqlx::Error> {
+    let revoked = sqlx::query!(
+        r#"UPDATE invites SET revoked_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE id = $1 AND revoked_at IS NULL;"#,
+        invite_id,
+    )
+    .execute(db)
+    .await?;
+    if revoked.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// The invite behind a code, if it is neither revoked nor expired.
+pub async fn find(conn: &mut sqlx::SqliteConnection, code: &str) -> Result<Option<Found>, sqlx::Error> {
+    let code_hash = tokens::hash(code.trim());
+    sqlx::query_as!(
+        Found,
+        r#"SELECT id AS "id!", email FROM invites
+        WHERE code_hash = $1 AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));"#,
+        code_hash,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+/// Use up one registration of an invite. Returns false when none are left.
+pub async fn consume(conn: &mut sqlx::SqliteConnection, invite_id: i64) -> Result<bool, sqlx::Error> {
+    let used = sqlx::query!(
+        r#"UPDATE invites SET uses = uses + 1
+        WHERE id = $1 AND uses < max_uses AND revoked_at IS NULL;"#,
+        invite_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(used.rows_affected() == 1)
+}
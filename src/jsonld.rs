@@ -0,0 +1,401 @@
+//! schema.org `Recipe` in JSON-LD, as recipe sites embed it in
+//! `<script type="application/ld+json">` blocks: read from saved pages and
+//! written for the API and the recipe page.
+
+use crate::*;
+
+use serde_json::{Value, json};
+use std::collections::HashSet;
+
+/// Bodies of the page's JSON-LD script blocks.
+fn scripts(html: &str) -> Vec<&str> {
+    // ASCII lowercasing keeps byte offsets, so positions found in `lower` index `html`
+    let lower = html.to_ascii_lowercase();
+    let mut blocks = Vec::new();
+    let mut at = 0;
+    while let Some(start) = lower[at..].find("<script").map(|i| at + i) {
+        let Some(open_end) = lower[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let Some(close) = lower[open_end..].find("</script").map(|i| open_end + i) else {
+            break;
+        };
+        if lower[start..open_end].contains("application/ld+json") {
+            blocks.push(&html[open_end..close]);
+        }
+        at = close;
+    }
+    blocks
+}
+
+/// Whether a node's `@type` is, or includes, `Recipe`.
+fn is_recipe(node: &Value) -> bool {
+    let is = |t: &Value| {
+        t.as_str()
+            .is_some_and(|t| t == "Recipe" || t.ends_with("/Recipe") || t.ends_with(":Recipe"))
+    };
+    match node.get("@type") {
+        Some(Value::Array(types)) => types.iter().any(is),
+        Some(t) => is(t),
+        None => false,
+    }
+}
+
+/// First `Recipe` node in a JSON-LD document, looking through arrays, `@graph`
+/// and `mainEntity`.
+fn find_recipe(node: &Value) -> Option<&Value> {
+    match node {
+        Value::Array(nodes) => nodes.iter().find_map(find_recipe),
+        Value::Object(_) if is_recipe(node) => Some(node),
+        Value::Object(fields) => ["@graph", "mainEntity"]
+            .iter()
+            .filter_map(|key| fields.get(*key))
+            .find_map(find_recipe),
+        _ => None,
+    }
+}
+
+/// Decode the character references recipe sites leave in JSON-LD strings.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&semi| semi <= 10).and_then(|semi| {
+            let name = &rest[1..semi];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, semi))
+        });
+        match decoded {
+            Some((c, semi)) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Plain text of a JSON-LD string: markup dropped, entities decoded and
+/// whitespace collapsed.
+fn clean(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                plain.push(' ');
+            }
+            _ if !in_tag => plain.push(c),
+            _ => (),
+        }
+    }
+    decode_entities(&plain)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Strings of a property that may be one string or a list of them.
+fn strings(node: Option<&Value>) -> Vec<String> {
+    match node {
+        Some(Value::String(s)) => vec![clean(s)],
+        Some(Value::Array(items)) => items.iter().flat_map(|item| strings(Some(item))).collect(),
+        Some(Value::Number(n)) => vec![n.to_string()],
+        Some(Value::Object(fields)) => strings(fields.get("name")),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|s| !s.is_empty())
+    .collect()
+}
+
+/// Instruction lines from a string, a list of strings, or `HowToStep`s grouped
+/// into `HowToSection`s, whose names become lines of their own.
+fn steps(node: Option<&Value>, lines: &mut Vec<String>) {
+    match node {
+        Some(Value::String(s)) => {
+            // A single string often has one step per line or paragraph
+            let s = s.replace("<br>", "\n").replace("<br/>", "\n").replace("</p>", "\n");
+            lines.extend(s.lines().map(clean).filter(|line| !line.is_empty()));
+        }
+        Some(Value::Array(items)) => {
+            for item in items {
+                steps(Some(item), lines);
+            }
+        }
+        Some(Value::Object(fields)) => {
+            if let Some(items) = fields.get("itemListElement") {
+                lines.extend(strings(fields.get("name")).into_iter().map(|name| format!("{}:", name)));
+                steps(Some(items), lines);
+            } else if let Some(text) = fields.get("text") {
+                steps(Some(text), lines);
+            } else {
+                steps(fields.get("name"), lines);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Servings from a `recipeYield` such as `4`, `"4 servings"` or `["4", "4 bowls"]`.
+fn servings(node: Option<&Value>) -> Option<i64> {
+    match node? {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => {
+            let digits: String = s
+                .chars()
+                .skip_while(|c| !c.is_ascii_digit())
+                .take_while(char::is_ascii_digit)
+                .collect();
+            digits.parse().ok()
+        }
+        Value::Array(items) => items.iter().find_map(|item| servings(Some(item))),
+        _ => None,
+    }
+    .filter(|&n| n > 0)
+}
+
+/// The schema.org `Recipe` embedded in a saved recipe page, with an id made
+/// from its name and tags from its keywords and categories.
+pub fn parse_html(html: &str) -> Result<JSONRecipe, JsonLdError> {
+    let documents: Vec<Value> = scripts(html)
+        .into_iter()
+        .filter_map(|block| serde_json::from_str(block.trim()).ok())
+        .collect();
+    let node = documents
+        .iter()
+        .find_map(find_recipe)
+        .ok_or(JsonLdError::NoRecipe)?;
+
+    let title = strings(node.get("name")).join(" ");
+    let id = recipe::slug(&title);
+    if id.is_empty() {
+        return Err(JsonLdError::Unnamed);
+    }
+    let ingredients = strings(node.get("recipeIngredient").or(node.get("ingredients"))).join("\n");
+    let mut instructions = Vec::new();
+    steps(node.get("recipeInstructions"), &mut instructions);
+    let source = strings(node.get("url"))
+        .into_iter()
+        .chain(strings(node.get("isBasedOn")))
+        .chain(strings(node.get("author")))
+        .next()
+        .unwrap_or_default();
+
+    let mut tags = HashSet::new();
+    let keywords = strings(node.get("keywords"));
+    let categories = strings(node.get("recipeCategory"));
+    for tag in keywords.iter().chain(&categories).flat_map(|s| s.split(',')) {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() {
+            tags.insert(tag);
+        }
+    }
+
+    Ok(JSONRecipe {
+        id,
+        title,
+        ingredients,
+        instructions: instructions.join("\n"),
+        source,
+        servings: servings(node.get("recipeYield")),
+        tags,
+        parsed_ingredients: Vec::new(),
+        created_by: None,
+        created_at: None,
+        updated_at: None,
+    })
+}
+
+/// Instructions as `HowToStep`s, grouped into a `HowToSection` under each line
+/// ending in `:`, the form [`parse_html`] reads sections into.
+fn how_to(instructions: &str) -> Vec<Value> {
+    let mut items = Vec::new();
+    let mut section: Option<(String, Vec<Value>)> = None;
+    for line in instructions.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(name) = line.strip_suffix(':') {
+            if let Some((name, steps)) = section.take() {
+                items.push(json!({ "@type": "HowToSection", "name": name, "itemListElement": steps }));
+            }
+            section = Some((name.to_string(), Vec::new()));
+            continue;
+        }
+        let step = json!({ "@type": "HowToStep", "text": line });
+        match &mut section {
+            Some((_, steps)) => steps.push(step),
+            None => items.push(step),
+        }
+    }
+    if let Some((name, steps)) = section {
+        items.push(json!({ "@type": "HowToSection", "name": name, "itemListElement": steps }));
+    }
+    items
+}
+
+/// A recipe as a schema.org `Recipe` document.
+///
+/// A source that is a link becomes `isBasedOn`; anything else is taken to name
+/// the author.
+pub fn document(recipe: &Recipe, tags: &[String]) -> Value {
+    let mut keywords: Vec<&str> = tags.iter().map(String::as_str).collect();
+    keywords.sort_unstable();
+    let mut doc = json!({
+        "@context": "https://schema.org",
+        "@type": "Recipe",
+        "identifier": recipe.id,
+        "name": recipe.title,
+        "recipeIngredient": recipe
+            .ingredients
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>(),
+        "recipeInstructions": how_to(&recipe.instructions),
+    });
+    if !keywords.is_empty() {
+        doc["keywords"] = json!(keywords.join(", "));
+    }
+    let source = recipe.recipe_source.trim();
+    if source.starts_with("http://") || source.starts_with("https://") {
+        doc["isBasedOn"] = json!(source);
+    } else if !source.is_empty() {
+        doc["author"] = json!({ "@type": "Person", "name": source });
+    }
+    if let Some(servings) = recipe.servings {
+        doc["recipeYield"] = json!(format!("{} servings", servings));
+    }
+    if !recipe.created_at.is_empty() {
+        doc["datePublished"] = json!(recipe.created_at);
+    }
+    if let Some(updated_at) = &recipe.updated_at {
+        doc["dateModified"] = json!(updated_at);
+    }
+    doc
+}
+
+/// Add the recipe in a saved page, returning the id it was given.
+pub async fn import_html(
+    db: &SqlitePool,
+    path: &std::path::Path,
+    author: &str,
+) -> Result<String, JsonLdError> {
+    let html = tokio::fs::read_to_string(path).await?;
+    let recipe = parse_html(&html)?;
+    Ok(recipe::add_unique(db, recipe, author).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A saved page embedding `ld` as its JSON-LD block.
+    fn page(ld: Value) -> String {
+        format!(
+            "<html><head><script type=\"application/ld+json\">{}</script></head><body></body></html>",
+            ld
+        )
+    }
+
+    #[test]
+    fn sections_become_named_lines() {
+        let recipe = parse_html(&page(json!({
+            "@type": "Recipe",
+            "name": "Layer Cake",
+            "recipeInstructions": [
+                {
+                    "@type": "HowToSection",
+                    "name": "Sponge",
+                    "itemListElement": [
+                        { "@type": "HowToStep", "text": "Whisk the eggs." },
+                        { "@type": "HowToStep", "text": "Fold in the flour." }
+                    ]
+                },
+                {
+                    "@type": "HowToSection",
+                    "name": "Icing",
+                    "itemListElement": [{ "@type": "HowToStep", "text": "Beat the butter." }]
+                }
+            ]
+        })))
+        .expect("recipe");
+        assert_eq!(
+            recipe.instructions,
+            "Sponge:\nWhisk the eggs.\nFold in the flour.\nIcing:\nBeat the butter."
+        );
+    }
+
+    #[test]
+    fn recipe_is_found_in_a_graph() {
+        let recipe = parse_html(&page(json!({
+            "@context": "https://schema.org",
+            "@graph": [
+                { "@type": "WebPage", "name": "Not this" },
+                { "@type": ["Recipe", "NewsArticle"], "name": "Tomato Soup", "recipeYield": "4 bowls" }
+            ]
+        })))
+        .expect("recipe");
+        assert_eq!(recipe.id, "tomato-soup");
+        assert_eq!(recipe.title, "Tomato Soup");
+        assert_eq!(recipe.servings, Some(4));
+    }
+
+    #[test]
+    fn entities_and_markup_are_decoded() {
+        let recipe = parse_html(&page(json!({
+            "@type": "Recipe",
+            "name": "Mac &amp; Cheese",
+            "recipeIngredient": ["200&#160;g macaroni", "&frac12; <b>cup</b> milk &#x2013; warm"],
+            "recipeInstructions": "Boil the pasta.<br>Stir in the cheese &amp; milk."
+        })))
+        .expect("recipe");
+        assert_eq!(recipe.title, "Mac & Cheese");
+        assert_eq!(recipe.id, "mac-cheese");
+        assert_eq!(recipe.ingredients, "200 g macaroni\n&frac12; cup milk – warm");
+        assert_eq!(recipe.instructions, "Boil the pasta.\nStir in the cheese & milk.");
+    }
+
+    #[test]
+    fn keywords_and_categories_become_tags() {
+        let recipe = parse_html(&page(json!({
+            "@type": "Recipe",
+            "name": "Pad Thai",
+            "keywords": "Noodles, quick , Thai",
+            "recipeCategory": ["Dinner", "thai"]
+        })))
+        .expect("recipe");
+        let tags: HashSet<String> = ["noodles", "quick", "thai", "dinner"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(recipe.tags, tags);
+    }
+
+    #[test]
+    fn page_without_a_recipe_is_rejected() {
+        let html = page(json!({ "@type": "WebPage", "name": "About us" }));
+        assert!(matches!(parse_html(&html), Err(JsonLdError::NoRecipe)));
+    }
+}
@@ -0,0 +1,210 @@
+//! Token signing and verification keys.
+//!
+//! Keys are listed in a manifest, `secrets/jwt_keys.json` unless `JWT_KEYS_FILE`
+//! says otherwise:
+//!
+//! ```json
+//! {
+//!     "signing_kid": "2025-06",
+//!     "keys": [
+//!         { "kid": "2025-06", "alg": "EdDSA",
+//!           "private_key": "secrets/jwt-2025-06.pem", "public_key": "secrets/jwt-2025-06.pub.pem" },
+//!         { "kid": "2025-01", "alg": "RS256", "public_key": "secrets/jwt-2025-01.pub.pem" }
+//!     ]
+//! }
+//! ```
+//!
+//! New tokens are signed with `signing_kid`; every listed key still verifies the
+//! tokens it signed, so a key can be retired once its tokens have expired. Without
+//! a manifest the HS512 secret signs and verifies as before; with one, the secret
+//! only verifies tokens that have no `kid`, until its file is removed.
+
+use crate::*;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, Header};
+use rsa::{
+    RsaPublicKey,
+    pkcs8::{DecodePublicKey, Document, ObjectIdentifier, SubjectPublicKeyInfoRef},
+    traits::PublicKeyParts,
+};
+
+/// Algorithm identifier of Ed25519 public keys.
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+#[derive(Deserialize)]
+struct Manifest {
+    signing_kid: String,
+    keys: Vec<ManifestKey>,
+}
+
+#[derive(Deserialize)]
+struct ManifestKey {
+    kid: String,
+    alg: Algorithm,
+    public_key: std::path::PathBuf,
+    private_key: Option<std::path::PathBuf>,
+}
+
+struct SigningKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+/// A key that verifies tokens carrying its `kid`.
+pub struct VerifyingKey {
+    kid: Option<String>,
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+}
+
+pub struct JwtKeys {
+    signing: SigningKey,
+    verifying: Vec<VerifyingKey>,
+    /// Public keys as a JWK set, for `/.well-known/jwks.json`.
+    jwks: serde_json::Value,
+}
+
+impl JwtKeys {
+    /// Sign and verify with one shared HS512 secret.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            signing: SigningKey {
+                kid: None,
+                algorithm: Algorithm::HS512,
+                key: EncodingKey::from_secret(secret),
+            },
+            verifying: vec![secret_key(secret)],
+            jwks: serde_json::json!({ "keys": [] }),
+        }
+    }
+
+    /// Sign claims with the current signing key.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.signing.algorithm);
+        header.kid = self.signing.kid.clone();
+        jsonwebtoken::encode(&header, claims, &self.signing.key)
+    }
+
+    /// The key for a token's `kid`, if it is one of ours.
+    pub fn verifying_key(&self, kid: Option<&str>) -> Option<&VerifyingKey> {
+        self.verifying.iter().find(|key| key.kid.as_deref() == kid)
+    }
+
+    pub fn jwks(&self) -> &serde_json::Value {
+        &self.jwks
+    }
+}
+
+fn secret_key(secret: &[u8]) -> VerifyingKey {
+    VerifyingKey {
+        kid: None,
+        algorithm: Algorithm::HS512,
+        key: DecodingKey::from_secret(secret),
+    }
+}
+
+/// Public JWK for a PEM encoded public key.
+fn jwk(kid: &str, alg: Algorithm, pem: &[u8]) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let pem = std::str::from_utf8(pem)?;
+    match alg {
+        Algorithm::EdDSA => {
+            let (_, der) = Document::from_pem(pem)?;
+            let info = SubjectPublicKeyInfoRef::try_from(der.as_bytes())?;
+            if info.algorithm.oid != ED25519_OID {
+                return Err(format!("key {}: not an Ed25519 public key", kid).into());
+            }
+            let x = info.subject_public_key.raw_bytes();
+            Ok(serde_json::json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": URL_SAFE_NO_PAD.encode(x),
+                "kid": kid,
+                "alg": "EdDSA",
+                "use": "sig",
+            }))
+        }
+        Algorithm::RS256 => {
+            let key = RsaPublicKey::from_public_key_pem(pem)?;
+            Ok(serde_json::json!({
+                "kty": "RSA",
+                "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+                "kid": kid,
+                "alg": "RS256",
+                "use": "sig",
+            }))
+        }
+        alg => Err(format!("key {}: unsupported algorithm {:?}", kid, alg).into()),
+    }
+}
+
+/// Load the keys in a manifest, keeping the HS512 secret for verification if given.
+async fn load(
+    manifest: &str,
+    secret: Option<String>,
+) -> Result<JwtKeys, Box<dyn std::error::Error>> {
+    let manifest: Manifest = serde_json::from_str(manifest)?;
+    let mut signing = None;
+    let mut verifying = Vec::new();
+    let mut jwks = Vec::new();
+    for entry in manifest.keys {
+        let public = tokio::fs::read(&entry.public_key).await?;
+        jwks.push(jwk(&entry.kid, entry.alg, &public)?);
+        let key = match entry.alg {
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(&public)?,
+            _ => DecodingKey::from_rsa_pem(&public)?,
+        };
+        verifying.push(VerifyingKey {
+            kid: Some(entry.kid.clone()),
+            algorithm: entry.alg,
+            key,
+        });
+
+        if entry.kid != manifest.signing_kid {
+            continue;
+        }
+        let path = entry
+            .private_key
+            .ok_or_else(|| format!("signing key {} has no private_key", entry.kid))?;
+        let private = tokio::fs::read(path).await?;
+        let key = match entry.alg {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private)?,
+            _ => EncodingKey::from_rsa_pem(&private)?,
+        };
+        signing = Some(SigningKey {
+            kid: Some(entry.kid),
+            algorithm: entry.alg,
+            key,
+        });
+    }
+    let signing = signing.ok_or_else(|| format!("no key with kid {}", manifest.signing_kid))?;
+    if let Some(secret) = secret {
+        verifying.push(secret_key(secret.as_bytes()));
+    }
+
+    Ok(JwtKeys {
+        signing,
+        verifying,
+        jwks: serde_json::json!({ "keys": jwks }),
+    })
+}
+
+/// Load the key manifest, or the HS512 secret when there is no manifest.
+pub async fn make_jwt_keys() -> Result<JwtKeys, Box<dyn std::error::Error>> {
+    let secret = authjwt::read_secret("JWT_SECRETFILE", "secrets/jwt_secret.txt").await;
+    let manifest_file =
+        std::env::var("JWT_KEYS_FILE").unwrap_or_else(|_| "secrets/jwt_keys.json".to_owned());
+    match tokio::fs::read_to_string(manifest_file).await {
+        Ok(manifest) => load(&manifest, secret.ok()).await,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(JwtKeys::new(secret?.as_bytes())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Public verification keys, so other services can check our tokens.
+pub async fn get_jwks(State(app_state): State<SharedAppState>) -> axum::response::Response {
+    let app_state = app_state.read().await;
+    Json(app_state.jwt_keys.jwks().clone()).into_response()
+}
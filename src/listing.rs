@@ -0,0 +1,176 @@
+use crate::*;
+
+use sqlx::{QueryBuilder, Sqlite};
+
+/// Largest page a listing will return.
+const MAX_LIMIT: i64 = 100;
+
+/// Field recipes are listed by.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Title,
+    Created,
+}
+
+impl SortKey {
+    /// SQL expression the listing is ordered and paged by.
+    fn column(self) -> &'static str {
+        match self {
+            SortKey::Title => "title COLLATE NOCASE",
+            SortKey::Created => "created_at",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Query parameters for listing recipes.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct ListParams {
+    /// Field to sort by.
+    pub sort: Option<SortKey>,
+    /// Sort direction.
+    pub order: Option<SortOrder>,
+    /// Recipes per page, at most 100.
+    pub limit: Option<i64>,
+    /// `next` cursor from the previous page.
+    pub cursor: Option<String>,
+    /// Tag expression such as `vegan AND NOT nuts`.
+    pub tags: Option<String>,
+    /// Only recipes whose source contains this text.
+    pub source: Option<String>,
+    /// Only recipes whose title starts with this text, ignoring case.
+    pub title_prefix: Option<String>,
+}
+
+/// One page of recipes.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecipePage {
+    /// Number of recipes matching the filters across all pages.
+    pub total: i64,
+    /// Cursor for the following page, absent on the last page.
+    pub next: Option<String>,
+    pub recipes: Vec<JSONRecipe>,
+}
+
+/// Position in a listing: the sort value and id of the last recipe seen.
+#[derive(Serialize, Deserialize)]
+struct Cursor(String, String);
+
+impl Cursor {
+    /// Opaque, URL-safe form of the cursor.
+    fn encode(&self) -> String {
+        let json = serde_json::to_string(self).expect("cursor serializes");
+        json.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn decode(s: &str) -> Result<Self, RecipeError> {
+        if !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return Err(RecipeError::InvalidCursor);
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| RecipeError::InvalidCursor)?;
+        serde_json::from_slice(&bytes).map_err(|_| RecipeError::InvalidCursor)
+    }
+}
+
+/// Escape `%`, `_` and `\` so text matches literally in a `LIKE ... ESCAPE '\'`.
+pub fn like_literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Append the filter conditions shared by the page and count queries.
+fn push_filters(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    params: &ListParams,
+    tags: Option<&TagQuery>,
+) {
+    builder.push(" WHERE 1");
+    if let Some(tags) = tags {
+        builder.push(" AND ");
+        tags.push_sql(builder);
+    }
+    if let Some(source) = &params.source {
+        builder
+            .push(" AND instr(lower(recipe_source), lower(")
+            .push_bind(source.clone())
+            .push(")) > 0");
+    }
+    if let Some(prefix) = &params.title_prefix {
+        builder
+            .push(" AND title LIKE ")
+            .push_bind(format!("{}%", like_literal(prefix)))
+            .push(" ESCAPE '\\'");
+    }
+}
+
+/// List recipes matching the filters, one page at a time.
+pub async fn list(db: &SqlitePool, params: &ListParams) -> Result<RecipePage, RecipeError> {
+    let tags = params.tags.as_deref().map(TagQuery::parse).transpose()?;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let sort = params.sort.unwrap_or_default();
+    let order = params.order.unwrap_or_default();
+    let limit = params.limit.unwrap_or(20).clamp(1, MAX_LIMIT);
+
+    let mut count = QueryBuilder::new("SELECT count(*) FROM recipes");
+    push_filters(&mut count, params, tags.as_ref());
+    let total: i64 = count.build_query_scalar().fetch_one(db).await?;
+
+    let column = sort.column();
+    let (cmp, dir) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+    let mut page = QueryBuilder::new(format!("SELECT {} AS key, id FROM recipes", column));
+    push_filters(&mut page, params, tags.as_ref());
+    if let Some(Cursor(key, id)) = cursor {
+        page.push(format!(" AND ({} {} ", column, cmp))
+            .push_bind(key.clone())
+            .push(format!(" OR ({} = ", column))
+            .push_bind(key)
+            .push(format!(" AND id {} ", cmp))
+            .push_bind(id)
+            .push("))");
+    }
+    page.push(format!(" ORDER BY {} {}, id {} LIMIT ", column, dir, dir))
+        .push_bind(limit + 1);
+    let mut rows: Vec<(String, String)> = page.build_query_as().fetch_all(db).await?;
+
+    let next = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|(key, id)| Cursor(key.clone(), id.clone()).encode())
+    } else {
+        None
+    };
+
+    let mut recipes = Vec::with_capacity(rows.len());
+    for (_, id) in rows {
+        let (recipe, tags) = recipe::get(db, &id).await?;
+        let ingredients = ingredient::load(db, &id).await?;
+        recipes.push(JSONRecipe::new(recipe, tags, ingredients));
+    }
+
+    Ok(RecipePage {
+        total,
+        next,
+        recipes,
+    })
+}
@@ -1,13 +1,35 @@
 mod api;
+mod apikeys;
+mod audit;
 mod authjwt;
+mod cooklang;
 mod error;
+mod export;
+mod import;
+mod ingredient;
+mod invites;
+mod jsonld;
+mod jwks;
+mod listing;
 mod recipe;
+mod revision;
+mod roles;
+mod search;
+mod tagquery;
 mod templates;
+mod throttle;
+mod tokens;
+mod units;
+mod users;
 mod web;
 
 use error::*;
+use ingredient::Ingredient;
 use recipe::*;
+use roles::{Require, Role};
+use tagquery::TagQuery;
 use templates::*;
+use units::UnitSystem;
 
 extern crate fastrand;
 extern crate log;
@@ -16,23 +38,23 @@ extern crate mime;
 use axum::{
     self,
     RequestPartsExt,
-    extract::{Json, Path, Query, State},
+    extract::{ConnectInfo, Json, Path, Query, State},
     http::{self, StatusCode},
     response::{self, IntoResponse},
     routing,
 };
 use axum_extra::{
-    headers::{authorization::Bearer, Authorization},
+    headers::{authorization::Bearer, Authorization, ETag, IfMatch, IfNoneMatch},
     TypedHeader,
 };
 use chrono::{prelude::*, TimeDelta};
 use clap::Parser;
 use jsonwebtoken::{EncodingKey, DecodingKey};
 use serde::{Serialize, Deserialize};
-use sqlx::{Row, SqlitePool};
-use std::sync::Arc;
+use sqlx::SqlitePool;
+use std::{net::SocketAddr, sync::Arc};
 use tokio::{net, sync::RwLock};
-use tower_http::{services, trace};
+use tower_http::{request_id, services, trace};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 use utoipa::{OpenApi, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -44,30 +66,75 @@ use utoipa_swagger_ui::SwaggerUi;
 struct Args {
     #[arg(short, long, name = "init-from")]
     init_from: Option<std::path::PathBuf>,
+    /// How `--init-from` treats recipes that already exist.
+    #[arg(long, value_enum, default_value_t)]
+    mode: import::Mode,
+    /// Report what `--init-from` would change without changing anything.
+    #[arg(long, name = "dry-run")]
+    dry_run: bool,
+    /// Give the user with this email the admin role, then exit.
+    #[arg(long, name = "grant-admin")]
+    grant_admin: Option<String>,
+    /// Print a new invite code good for this many registrations, then exit.
+    #[arg(long, name = "create-invite", num_args = 0..=1, default_missing_value = "1")]
+    create_invite: Option<i64>,
+    /// Add the schema.org recipe embedded in each of these saved HTML pages, then exit.
+    #[arg(long, name = "import-html", num_args = 1..)]
+    import_html: Vec<std::path::PathBuf>,
+    /// Add the recipe in each of these Cooklang `.cook` files, then exit.
+    #[arg(long, name = "import-cook", num_args = 1..)]
+    import_cook: Vec<std::path::PathBuf>,
+    /// Print the recipe with this id as Cooklang, then exit.
+    #[arg(long, name = "export-cook")]
+    export_cook: Option<String>,
+    /// Write every recipe to this file, or to stdout for `-`, then exit.
+    #[arg(long)]
+    export: Option<std::path::PathBuf>,
+    /// Layout of `--export`.
+    #[arg(long, name = "export-format", value_enum, default_value_t)]
+    export_format: export::Format,
 }
 
 struct AppState {
     db: SqlitePool,
-    jwt_keys: authjwt::JwtKeys,
-    reg_key: String,
+    jwt_keys: jwks::JwtKeys,
+    token_config: authjwt::TokenConfig,
     current_recipe: Recipe,
 }
 
 type SharedAppState = Arc<RwLock<AppState>>;
 
+/// A migrated database of its own for each test.
+#[cfg(test)]
+async fn test_db() -> SqlitePool {
+    // One connection, since every connection to `:memory:` is its own database
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("open in-memory database");
+    sqlx::migrate!().run(&db).await.expect("run migrations");
+    db
+}
+
 impl AppState {
-    pub fn new(db: SqlitePool, jwt_keys: authjwt::JwtKeys, reg_key: String) -> Self {
+    pub fn new(db: SqlitePool, jwt_keys: jwks::JwtKeys, token_config: authjwt::TokenConfig) -> Self {
         let current_recipe = Recipe {
             id: "boil".to_string(),
             title: "Boil Water".to_string(),
             ingredients: "100 ml water".to_string(),
             instructions: "Add water to pot.\nHeat pot until water boils.".to_string(),
             recipe_source: "Jason Gonzales".to_string(),
+            servings: None,
+            version: 0,
+            updated_at: None,
+            created_at: String::new(),
+            created_by: None,
         };
         Self {
             db,
             jwt_keys,
-            reg_key,
+            token_config,
             current_recipe,
         }
     }
@@ -85,59 +152,104 @@ async fn serve() -> Result<(), Box<dyn std::error::Error>> {
     let db = SqlitePool::connect("sqlite://db/recipes.db").await?;
     sqlx::migrate!().run(&db).await?;
 
+    if let Some(email) = args.grant_admin {
+        let user = users::grant_admin(&db, &email).await?;
+        println!("{} <{}> is now an admin", user.display_name, user.email);
+        return Ok(());
+    }
+
+    if let Some(max_uses) = args.create_invite {
+        let request = invites::NewInvite {
+            max_uses: Some(max_uses),
+            ..Default::default()
+        };
+        let invite = invites::create(&db, None, request).await?;
+        let expires_at = invite.info.expires_at.unwrap_or_default();
+        println!("{} (uses: {}, expires {})", invite.code, max_uses, expires_at);
+        return Ok(());
+    }
+
+    // Everything below reads or writes recipes
+    ingredient::backfill(&db).await?;
+
+    if let Some(path) = args.export {
+        export::write(&db, &path, args.export_format).await?;
+        return Ok(());
+    }
+
+    if !args.import_html.is_empty() {
+        let mut failed = false;
+        for path in &args.import_html {
+            match jsonld::import_html(&db, path, "import-html").await {
+                Ok(id) => println!("added {} from {}", id, path.display()),
+                Err(e) => {
+                    eprintln!("error: {}: {}", path.display(), e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if !args.import_cook.is_empty() {
+        let mut failed = false;
+        for path in &args.import_cook {
+            match cooklang::import_file(&db, path, "import-cook").await {
+                Ok(id) => println!("added {} from {}", id, path.display()),
+                Err(e) => {
+                    eprintln!("error: {}: {}", path.display(), e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(id) = args.export_cook {
+        let (recipe, tags) = recipe::get(&db, &id).await?;
+        print!("{}", cooklang::render(&recipe, &tags));
+        return Ok(());
+    }
+
     if let Some(path) = args.init_from {
         let recipes = read_recipes(path)?;
-        'next_recipe: for rr in recipes {
-            let mut tx = db.begin().await?;
-            let (r, tags) = rr.to_recipe();
-            let recipe_insert = sqlx::query!(
-                "INSERT INTO recipes (id, title, ingredients, instructions, recipe_source) VALUES ($1, $2, $3, $4, $5);",
-                r.id,
-                r.title,
-                r.ingredients,
-                r.instructions,
-                r.recipe_source,
-            )
-            .execute(&mut *tx)
-            .await;
-            if let Err(e) = recipe_insert {
-                eprintln!("error: recipe insert: {}: {}", r.id, e);
-                tx.rollback().await?;
-                continue;
-            };
-            for t in tags {
-                let tag_insert = sqlx::query!(
-                    "INSERT INTO tags (recipe_id, tag) VALUES ($1, $2);",
-                    r.id,
-                    t,
-                )
-                .execute(&mut *tx)
-                .await;
-                if let Err(e) = tag_insert {
-                    eprintln!("error: tag insert: {} {}: {}", r.id, t, e);
-                    tx.rollback().await?;
-                    continue 'next_recipe;
-                };
+        let importer = import::Importer {
+            author: "init-from",
+            owner: None,
+            request_id: None,
+        };
+        let report = import::run(&db, &recipes, args.mode, args.dry_run, &importer).await?;
+        for recipe in &report.recipes {
+            match &recipe.reason {
+                Some(reason) => eprintln!("{}: {}: {}", recipe.outcome, recipe.id, reason),
+                None => eprintln!("{}: {}", recipe.outcome, recipe.id),
             }
-            tx.commit().await?;
+        }
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if report.summary.failed > 0 {
+            std::process::exit(1);
         }
         return Ok(());
     }
     
     // Keys
-    let jwt_keys = authjwt::make_jwt_keys().await.unwrap_or_else(|_| {
-        tracing::error!("make jwt keys");
+    let jwt_keys = jwks::make_jwt_keys().await.unwrap_or_else(|e| {
+        tracing::error!("make jwt keys: {}", e);
         std::process::exit(1);
     });
 
-    let reg_key = authjwt::read_secret("REG_CODE", "secrets/reg_code.txt")
-        .await
-        .unwrap_or_else(|_| {
-            tracing::error!("read secret: reg code");
-            std::process::exit(1);
-        });
+    let token_config = authjwt::TokenConfig::from_env().unwrap_or_else(|e| {
+        tracing::error!("token config: {}", e);
+        std::process::exit(1);
+    });
 
-    let app_state = AppState::new(db, jwt_keys, reg_key);
+    let app_state = AppState::new(db, jwt_keys, token_config);
     let state = Arc::new(RwLock::new(app_state));
 
     // RUST_LOG is the default env variable
@@ -171,6 +283,7 @@ async fn serve() -> Result<(), Box<dyn std::error::Error>> {
     // Website Routing
     let app = axum::Router::new()
         .route("/", routing::get(web::get_recipe))
+        .route("/.well-known/jwks.json", routing::get(jwks::get_jwks))
         // NOTE: axum talks to tower-http
         .route_service(
             "/recipe.css",
@@ -187,10 +300,13 @@ async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         .fallback(handler_404)
         .layer(cors)
         .layer(trace_layer)
+        .layer(request_id::PropagateRequestIdLayer::x_request_id())
+        .layer(request_id::SetRequestIdLayer::x_request_id(request_id::MakeRequestUuid))
+        .layer(axum::middleware::map_request(audit::take_client_request_id))
         .with_state(state);
 
     let listener = net::TcpListener::bind("127.0.0.1:3000").await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
 
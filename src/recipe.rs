@@ -12,8 +12,25 @@ pub struct JSONRecipe {
     pub ingredients: String,
     pub instructions: String,
     pub source: String,
+    /// Number of servings the recipe makes, when known.
+    #[serde(default, alias = "yield")]
+    pub servings: Option<i64>,
     /// Tags start attached from the .json file
     pub tags: HashSet<String>,
+    /// Ingredient lines parsed into quantity, unit, item and note.
+    #[serde(default, skip_deserializing)]
+    #[schema(read_only)]
+    pub parsed_ingredients: Vec<Ingredient>,
+    /// Id of the user who added the recipe; absent for seeded recipes.
+    #[serde(default, skip_deserializing)]
+    #[schema(read_only, example = "42")]
+    pub created_by: Option<String>,
+    #[serde(default, skip_deserializing)]
+    #[schema(read_only, example = "2025-06-01T12:00:00Z")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_deserializing)]
+    #[schema(read_only, example = "2025-06-01T12:00:00Z")]
+    pub updated_at: Option<String>,
 }
 
 /// Represents recipe stored in the database.
@@ -24,6 +41,67 @@ pub struct Recipe {
     pub ingredients: String,
     pub instructions: String,
     pub recipe_source: String,
+    pub servings: Option<i64>,
+    /// Incremented on every write; the recipe's ETag.
+    pub version: i64,
+    pub updated_at: Option<String>,
+    pub created_at: String,
+    /// Id of the user who added the recipe.
+    pub created_by: Option<String>,
+}
+
+/// Partial update of a recipe. Fields left out are unchanged.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RecipePatch {
+    pub title: Option<String>,
+    pub ingredients: Option<String>,
+    pub instructions: Option<String>,
+    pub source: Option<String>,
+    /// New number of servings, or `null` to clear it.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i64>)]
+    pub servings: Option<Option<i64>>,
+    /// Replace the whole tag set before applying `add_tags` and `remove_tags`.
+    pub tags: Option<HashSet<String>>,
+    #[serde(default)]
+    pub add_tags: HashSet<String>,
+    #[serde(default)]
+    pub remove_tags: HashSet<String>,
+}
+
+/// A field given in the body, even as `null`, so `None` means it was left out.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Query parameters that resize a recipe by multiplier or target servings.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct ScaleParams {
+    /// Multiply every ingredient quantity by this factor.
+    pub scale: Option<f64>,
+    /// Rescale the recipe to make this many servings.
+    pub servings: Option<i64>,
+}
+
+impl ScaleParams {
+    /// Factor to multiply quantities by, or `None` when no scaling was asked for.
+    pub fn factor(&self, recipe_servings: Option<i64>) -> Result<Option<f64>, RecipeError> {
+        match (self.scale, self.servings) {
+            (Some(_), Some(_)) => Err(RecipeError::InvalidScale("use either scale or servings")),
+            (Some(scale), None) if scale.is_finite() && scale > 0.0 => Ok(Some(scale)),
+            (Some(_), None) => Err(RecipeError::InvalidScale("scale must be positive")),
+            (None, Some(target)) => match recipe_servings {
+                _ if target <= 0 => Err(RecipeError::InvalidScale("servings must be positive")),
+                Some(servings) if servings > 0 => Ok(Some(target as f64 / servings as f64)),
+                _ => Err(RecipeError::InvalidScale("recipe has no servings to scale from")),
+            },
+            (None, None) => Ok(None),
+        }
+    }
 }
 
 /// Reads JSON file and returns JSON recipes
@@ -56,72 +134,309 @@ pub async fn get_random(db: &SqlitePool) -> Result<(Recipe, Vec<String>), sqlx::
     get(db, &id).await
 }
 
-/// Get random recipe from given tags.
-pub async fn get_random_from_tags(
+/// Get up to `limit` random recipes matching a tag query.
+pub async fn find_by_tags(
     db: &SqlitePool,
-    tags: Vec<String>,
-) -> Result<(Recipe, Vec<String>), sqlx::Error> {
-    let mut tx = db.begin().await?;
-    sqlx::query("DROP TABLE IF EXISTS qtags;")
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("CREATE TEMPORARY TABLE qtags (tag VARCHR(200));")
-        .execute(&mut *tx)
-        .await?;
-    for tag in tags {
-        sqlx::query("INSERT INTO qtags VALUES ($1);")
-            .bind(tag)
-            .execute(&mut *tx)
-            .await?;
+    query: &TagQuery,
+    limit: i64,
+) -> Result<Vec<(Recipe, Vec<String>)>, sqlx::Error> {
+    let mut builder = sqlx::QueryBuilder::new("SELECT id FROM recipes WHERE ");
+    query.push_sql(&mut builder);
+    builder.push(" ORDER BY RANDOM() LIMIT ").push_bind(limit);
+
+    let ids: Vec<String> = builder.build_query_scalar().fetch_all(db).await?;
+    let mut recipes = Vec::with_capacity(ids.len());
+    for id in ids {
+        recipes.push(get(db, &id).await?);
     }
+    Ok(recipes)
+}
 
-    let row = sqlx::query("SELECT DISTINCT recipe_id FROM tags JOIN qtags ON tags.tag = qtags.tag ORDER BY RANDOM() LIMIT 1;")
-        .fetch_optional(&mut *tx)
-        .await?;
+/// Get random recipe matching a tag query.
+pub async fn get_random_from_tags(
+    db: &SqlitePool,
+    query: &TagQuery,
+) -> Result<(Recipe, Vec<String>), sqlx::Error> {
+    find_by_tags(db, query, 1)
+        .await?
+        .pop()
+        .ok_or(sqlx::Error::RowNotFound)
+}
 
-    tx.commit().await?;
+/// Add recipe to recipes table and tags table in database.
+pub async fn add(db: &SqlitePool, recipe: JSONRecipe, author: &str) -> Result<(), sqlx::Error> {
+    let mut jtx = db.begin().await?;
+    let (mut new, _) = recipe.to_recipe();
+    new.created_by = Some(author.to_string());
+    insert(&mut jtx, &new, &recipe.tags, author).await?;
+    jtx.commit().await?;
+    Ok(())
+}
 
-    if let Some(row) = row {
-        let id: String = row.get("recipe_id");
-        get(db, &id).await
-    } else {
-        Err(sqlx::Error::RowNotFound)
+/// Add a recipe under its id or, when that is taken, the id with the first free
+/// `-2`, `-3`, ... suffix. Returns the id it was given.
+///
+/// For recipes imported from files: `author` goes on the first revision only,
+/// and the recipe is left without an owner, like those `--init-from` seeds.
+pub async fn add_unique(
+    db: &SqlitePool,
+    mut recipe: JSONRecipe,
+    author: &str,
+) -> Result<String, sqlx::Error> {
+    let base = recipe.id.clone();
+    for n in 2.. {
+        // Try the insert itself rather than checking first, so an id taken by
+        // a concurrent add in between moves on to the next suffix.
+        let (new, _) = recipe.to_recipe();
+        let mut tx = db.begin().await?;
+        match insert(&mut tx, &new, &recipe.tags, author).await {
+            Ok(()) => {
+                tx.commit().await?;
+                break;
+            }
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                recipe.id = format!("{}-{}", base, n);
+            }
+            Err(e) => return Err(e),
+        }
     }
+    Ok(recipe.id)
 }
 
-/// Add recipe to recipes table and tags table in database.
-pub async fn add(db: &SqlitePool, recipe: JSONRecipe) -> Result<(), sqlx::Error> {
-    let mut jtx = db.begin().await?;
+/// Recipe id made from a title, such as `crispy-tofu-pad-thai`.
+pub fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if c == '\'' || c == '’' {
+            continue;
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
 
+/// Insert a new recipe row with its tags and ingredient rows, recorded as its
+/// first revision by `author`.
+///
+/// An id that belonged to a deleted recipe carries on from that recipe's last
+/// revision, so its history is kept and old ETags never match the new recipe.
+pub async fn insert(
+    conn: &mut sqlx::SqliteConnection,
+    recipe: &Recipe,
+    tags: &HashSet<String>,
+    author: &str,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"INSERT INTO recipes
-        (id, title, ingredients, instructions, recipe_source)
-        VALUES ($1, $2, $3, $4, $5);"#,
+        (id, title, ingredients, instructions, recipe_source, servings, version,
+            updated_at, created_at, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6,
+            COALESCE((SELECT MAX(version) FROM recipe_revisions WHERE recipe_id = $1), 0) + 1,
+            strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), $7);"#,
         recipe.id,
         recipe.title,
         recipe.ingredients,
         recipe.instructions,
-        recipe.source,
+        recipe.recipe_source,
+        recipe.servings,
+        recipe.created_by,
     )
-    .execute(&mut *jtx)
+    .execute(&mut *conn)
     .await?;
 
-    for tag in recipe.tags {
+    for tag in tags {
         sqlx::query!(
             r#"INSERT INTO tags (recipe_id, tag) VALUES ($1, $2);"#,
             recipe.id,
             tag,
         )
-            .execute(&mut *jtx)
+            .execute(&mut *conn)
             .await?;
     }
 
-    jtx.commit().await?;
+    ingredient::store(&mut *conn, &recipe.id, &recipe.ingredients).await?;
+    revision::record(conn, &recipe.id, author).await
+}
+
+/// Overwrite an existing recipe row and replace its tags and ingredient rows.
+///
+/// The row is only written while it is still at `version`; otherwise nothing
+/// changes and `RowNotFound` is returned. The result is recorded as a new revision.
+pub async fn write(
+    conn: &mut sqlx::SqliteConnection,
+    recipe: &Recipe,
+    tags: &HashSet<String>,
+    version: i64,
+    author: &str,
+) -> Result<(), sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"UPDATE recipes
+        SET title = $2, ingredients = $3, instructions = $4, recipe_source = $5, servings = $6,
+            version = version + 1, updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE id = $1 AND version = $7;"#,
+        recipe.id,
+        recipe.title,
+        recipe.ingredients,
+        recipe.instructions,
+        recipe.recipe_source,
+        recipe.servings,
+        version,
+    )
+    .execute(&mut *conn)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    sqlx::query!("DELETE FROM tags WHERE recipe_id = $1;", recipe.id)
+        .execute(&mut *conn)
+        .await?;
+    for tag in tags {
+        sqlx::query!(
+            r#"INSERT INTO tags (recipe_id, tag) VALUES ($1, $2);"#,
+            recipe.id,
+            tag,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    ingredient::store(&mut *conn, &recipe.id, &recipe.ingredients).await?;
+    revision::record(conn, &recipe.id, author).await
+}
+
+/// Current version of a recipe, for checking preconditions before a write.
+pub async fn version(db: &SqlitePool, recipe_id: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!("SELECT version FROM recipes WHERE id = $1;", recipe_id)
+        .fetch_one(db)
+        .await
+}
+
+/// Id of the user who added a recipe, if it has one.
+pub async fn owner(db: &SqlitePool, recipe_id: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT created_by FROM recipes WHERE id = $1;", recipe_id)
+        .fetch_one(db)
+        .await
+}
+
+/// Replace every field and the tag set of a recipe that is still at `version`.
+pub async fn update(
+    db: &SqlitePool,
+    recipe_id: &str,
+    recipe: JSONRecipe,
+    version: i64,
+    author: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let (mut updated, _) = recipe.to_recipe();
+    updated.id = recipe_id.to_string();
+    write(&mut tx, &updated, &recipe.tags, version, author).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Merge a partial update into a recipe that is still at `version`.
+pub async fn patch(
+    db: &SqlitePool,
+    recipe_id: &str,
+    patch: RecipePatch,
+    version: i64,
+    author: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let mut recipe = sqlx::query_as!(Recipe, "SELECT * FROM recipes WHERE id = $1;", recipe_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let mut tags: HashSet<String> = match patch.tags {
+        Some(tags) => tags,
+        None => sqlx::query_scalar!("SELECT tag FROM tags WHERE recipe_id = $1;", recipe_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect(),
+    };
+    tags.extend(patch.add_tags);
+    tags.retain(|tag| !patch.remove_tags.contains(tag));
+
+    if let Some(title) = patch.title {
+        recipe.title = title;
+    }
+    if let Some(ingredients) = patch.ingredients {
+        recipe.ingredients = ingredients;
+    }
+    if let Some(instructions) = patch.instructions {
+        recipe.instructions = instructions;
+    }
+    if let Some(source) = patch.source {
+        recipe.recipe_source = source;
+    }
+    if let Some(servings) = patch.servings {
+        recipe.servings = servings;
+    }
+
+    write(&mut tx, &recipe, &tags, version, author).await?;
+    tx.commit().await?;
     Ok(())
 }
 
+/// Remove a recipe that is still at `version`, along with its tags and ingredient rows.
+pub async fn delete(db: &SqlitePool, recipe_id: &str, version: i64) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    remove(&mut tx, recipe_id, version).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Delete a recipe row that is still at `version` with its tags and ingredient
+/// rows, or return `RowNotFound`. Its revisions are kept.
+pub async fn remove(
+    conn: &mut sqlx::SqliteConnection,
+    recipe_id: &str,
+    version: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM tags WHERE recipe_id = $1;", recipe_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM ingredients WHERE recipe_id = $1;", recipe_id)
+        .execute(&mut *conn)
+        .await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM recipes WHERE id = $1 AND version = $2;",
+        recipe_id,
+        version,
+    )
+    .execute(&mut *conn)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+impl Recipe {
+    /// Multiply ingredient quantities and servings by `factor`.
+    pub fn scale(&mut self, factor: f64) {
+        self.ingredients = ingredient::scale_lines(&self.ingredients, factor);
+        self.servings = self
+            .servings
+            .map(|s| ((s as f64 * factor).round() as i64).max(1));
+    }
+
+    /// Rewrite ingredient units and oven temperatures into `system`.
+    pub fn convert_units(&mut self, system: UnitSystem) {
+        self.ingredients = units::convert_lines(&self.ingredients, system);
+        self.instructions = units::convert_temperatures(&self.instructions, system);
+    }
+}
+
 impl JSONRecipe {
-    pub fn new(recipe: Recipe, tags: Vec<String>) -> Self {
+    /// A recipe with its tags and the ingredient rows stored for it, as
+    /// [`ingredient::load`] returns them.
+    pub fn new(recipe: Recipe, tags: Vec<String>, parsed_ingredients: Vec<Ingredient>) -> Self {
         let tags = tags.into_iter().collect();
         Self {
             id: recipe.id,
@@ -129,7 +444,12 @@ impl JSONRecipe {
             ingredients: recipe.ingredients,
             instructions: recipe.instructions,
             source: recipe.recipe_source,
+            servings: recipe.servings,
             tags,
+            parsed_ingredients,
+            created_by: recipe.created_by,
+            created_at: Some(recipe.created_at).filter(|at| !at.is_empty()),
+            updated_at: recipe.updated_at,
         }
     }
 
@@ -141,6 +461,11 @@ impl JSONRecipe {
             ingredients: self.ingredients.clone(),
             instructions: self.instructions.clone(),
             recipe_source: self.source.clone(),
+            servings: self.servings,
+            version: 0,
+            updated_at: None,
+            created_at: String::new(),
+            created_by: None,
         };
 
         let tags = self.tags.iter().map(String::deref);
@@ -153,3 +478,68 @@ impl axum::response::IntoResponse for &JSONRecipe {
         (http::StatusCode::OK, axum::Json(&self)).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pancakes() -> JSONRecipe {
+        serde_json::from_value(serde_json::json!({
+            "id": "pancakes",
+            "title": "Pancakes",
+            "ingredients": "2 cups flour\n2 eggs",
+            "instructions": "Mix.\nFry.",
+            "source": "test",
+            "tags": ["breakfast"],
+        }))
+        .expect("valid recipe")
+    }
+
+    #[tokio::test]
+    async fn deleted_id_can_be_added_again() {
+        let db = test_db().await;
+        add(&db, pancakes(), "tester").await.expect("first add");
+        let current = version(&db, "pancakes").await.expect("version");
+        delete(&db, "pancakes", current).await.expect("delete");
+
+        add(&db, pancakes(), "tester").await.expect("add after delete");
+        assert_eq!(version(&db, "pancakes").await.expect("version"), current + 1);
+        let history = revision::list(&db, "pancakes").await.expect("revisions");
+        assert_eq!(history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn add_unique_suffixes_taken_ids_without_an_owner() {
+        let db = test_db().await;
+        add(&db, pancakes(), "tester").await.expect("add");
+
+        let id = add_unique(&db, pancakes(), "import-html").await.expect("add_unique");
+        assert_eq!(id, "pancakes-2");
+        let (recipe, _) = get(&db, &id).await.expect("get");
+        assert_eq!(recipe.created_by, None);
+        let history = revision::list(&db, &id).await.expect("revisions");
+        assert_eq!(history[0].author, "import-html");
+    }
+
+    #[tokio::test]
+    async fn add_unique_moves_past_every_taken_suffix() {
+        let db = test_db().await;
+        add(&db, pancakes(), "tester").await.expect("add");
+        let mut taken = pancakes();
+        taken.id = "pancakes-2".to_string();
+        add(&db, taken, "tester").await.expect("add");
+
+        let id = add_unique(&db, pancakes(), "import-html").await.expect("add_unique");
+        assert_eq!(id, "pancakes-3");
+    }
+
+    #[test]
+    fn patch_tells_null_servings_from_left_out() {
+        let patch: RecipePatch = serde_json::from_str(r#"{"servings": null}"#).expect("patch");
+        assert_eq!(patch.servings, Some(None));
+        let patch: RecipePatch = serde_json::from_str(r#"{"servings": 6}"#).expect("patch");
+        assert_eq!(patch.servings, Some(Some(6)));
+        let patch: RecipePatch = serde_json::from_str(r#"{"title": "Crepes"}"#).expect("patch");
+        assert_eq!(patch.servings, None);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::*;
+
+/// A stored snapshot of a recipe after one write.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Revision {
+    /// Recipe version this snapshot was taken at.
+    pub version: i64,
+    #[schema(example = "First Last <firstlast@example.com>")]
+    pub author: String,
+    #[schema(example = "2025-06-01T12:00:00Z")]
+    pub created_at: String,
+    pub recipe: JSONRecipe,
+}
+
+/// Revision metadata without the snapshot, for listing history.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevisionSummary {
+    pub version: i64,
+    pub author: String,
+    pub created_at: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a line-level diff.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Line diffs of the ingredients and instructions between two revisions.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevisionDiff {
+    pub from: i64,
+    pub to: i64,
+    pub ingredients: Vec<DiffLine>,
+    pub instructions: Vec<DiffLine>,
+}
+
+/// Snapshot the recipe as it now stands in `conn` under its current version.
+pub async fn record(
+    conn: &mut sqlx::SqliteConnection,
+    recipe_id: &str,
+    author: &str,
+) -> Result<(), sqlx::Error> {
+    let recipe = sqlx::query_as!(Recipe, "SELECT * FROM recipes WHERE id = $1;", recipe_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let tags = sqlx::query_scalar!("SELECT tag FROM tags WHERE recipe_id = $1;", recipe_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    let ingredients = ingredient::load(&mut *conn, recipe_id).await?;
+    let version = recipe.version;
+    let snapshot = JSONRecipe::new(recipe, tags, ingredients);
+    let snapshot = serde_json::to_string(&snapshot).map_err(|e| sqlx::Error::Encode(e.into()))?;
+
+    sqlx::query!(
+        r#"INSERT INTO recipe_revisions (recipe_id, version, snapshot, author, created_at)
+        VALUES ($1, $2, $3, $4, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));"#,
+        recipe_id,
+        version,
+        snapshot,
+        author,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// List the revisions of a recipe, oldest first.
+pub async fn list(db: &SqlitePool, recipe_id: &str) -> Result<Vec<RevisionSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        RevisionSummary,
+        r#"SELECT version, author, created_at, snapshot ->> '$.title' AS "title!: String"
+        FROM recipe_revisions WHERE recipe_id = $1 ORDER BY version;"#,
+        recipe_id,
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Fetch one revision of a recipe with its full snapshot.
+pub async fn get(db: &SqlitePool, recipe_id: &str, version: i64) -> Result<Revision, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT version, author, created_at, snapshot
+        FROM recipe_revisions WHERE recipe_id = $1 AND version = $2;"#,
+        recipe_id,
+        version,
+    )
+    .fetch_one(db)
+    .await?;
+    let recipe = serde_json::from_str(&row.snapshot).map_err(|e| sqlx::Error::Decode(e.into()))?;
+
+    Ok(Revision {
+        version: row.version,
+        author: row.author,
+        created_at: row.created_at,
+        recipe,
+    })
+}
+
+/// Line-level diff of `old` against `new` using a longest common subsequence.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] is the LCS length of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |op, text: &str| DiffLine {
+        op,
+        text: text.to_string(),
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(line(DiffOp::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push(line(DiffOp::Delete, old[i]));
+            i += 1;
+        } else {
+            diff.push(line(DiffOp::Insert, new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|text| line(DiffOp::Delete, text)));
+    diff.extend(new[j..].iter().map(|text| line(DiffOp::Insert, text)));
+    diff
+}
+
+/// Diff the ingredients and instructions of two revisions of a recipe.
+pub async fn diff(
+    db: &SqlitePool,
+    recipe_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<RevisionDiff, sqlx::Error> {
+    let old = get(db, recipe_id, from).await?.recipe;
+    let new = get(db, recipe_id, to).await?.recipe;
+    Ok(RevisionDiff {
+        from,
+        to,
+        ingredients: diff_lines(&old.ingredients, &new.ingredients),
+        instructions: diff_lines(&old.instructions, &new.instructions),
+    })
+}
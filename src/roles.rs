@@ -0,0 +1,94 @@
+use crate::*;
+
+use std::marker::PhantomData;
+
+/// What a user may do, each role including everything below it.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+    sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Role {
+    /// Read only. Tokens issued before roles existed fall back to this.
+    #[default]
+    Viewer,
+    /// Add recipes, and edit and delete their own.
+    Contributor,
+    /// Also edit and delete anyone's recipes.
+    Editor,
+    /// Also manage users.
+    Admin,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Contributor => write!(f, "contributor"),
+            Role::Editor => write!(f, "editor"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Type-level role for [`Require`], with the scope an API key needs in its place.
+pub trait MinRole {
+    const ROLE: Role;
+    const SCOPE: apikeys::Scope;
+}
+
+pub struct Viewer;
+pub struct Contributor;
+pub struct Admin;
+
+impl MinRole for Viewer {
+    const ROLE: Role = Role::Viewer;
+    const SCOPE: apikeys::Scope = apikeys::Scope::RecipesRead;
+}
+
+impl MinRole for Contributor {
+    const ROLE: Role = Role::Contributor;
+    const SCOPE: apikeys::Scope = apikeys::Scope::RecipesWrite;
+}
+
+impl MinRole for Admin {
+    const ROLE: Role = Role::Admin;
+    const SCOPE: apikeys::Scope = apikeys::Scope::Admin;
+}
+
+/// Extracts the token's claims, rejecting tokens whose role is below `R` with 403.
+///
+/// API keys also need `R`'s scope, on top of their owner having the role.
+///
+/// ```ignore
+/// async fn handler(Require(claims, _): Require<roles::Admin>) { ... }
+/// ```
+pub struct Require<R: MinRole>(pub authjwt::Claims, pub PhantomData<R>);
+
+impl<R: MinRole> axum::extract::FromRequestParts<SharedAppState> for Require<R> {
+    type Rejection = authjwt::AuthError;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &SharedAppState,
+    ) -> Result<Self, Self::Rejection> {
+        let claims: authjwt::Claims = parts.extract_with_state(state).await?;
+        let refused = if claims.role < R::ROLE {
+            authjwt::AuthError::Forbidden(R::ROLE)
+        } else if !claims.has_scope(R::SCOPE) {
+            authjwt::AuthError::MissingScope(R::SCOPE)
+        } else {
+            return Ok(Require(claims, PhantomData));
+        };
+
+        let app_state = state.read().await;
+        let request_id = audit::RequestId::from_headers(&parts.headers);
+        let event = audit::Event::new("auth.forbidden")
+            .actor(&claims.sub)
+            .target(parts.uri.path())
+            .after(serde_json::json!({ "reason": refused.to_string() }));
+        audit::record_rejection(&app_state.db, &request_id, audit::client_ip(parts), event).await;
+        Err(refused)
+    }
+}
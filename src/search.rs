@@ -0,0 +1,104 @@
+use crate::*;
+
+/// Largest page size a search will return.
+const MAX_PER_PAGE: i64 = 50;
+/// Last page a search will go to, which keeps the offset from overflowing.
+const MAX_PAGE: i64 = 10_000;
+
+/// Query parameters for a full-text recipe search.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+pub struct SearchParams {
+    /// Words to search for in titles, ingredients and instructions.
+    pub q: String,
+    /// Page number, starting at 1, at most 10000.
+    pub page: Option<i64>,
+    /// Results per page, at most 50.
+    pub per_page: Option<i64>,
+}
+
+/// One ranked search result.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct SearchHit {
+    pub id: String,
+    pub title: String,
+    /// HTML fragment of the best matching text, with matches wrapped in `<mark>`.
+    #[schema(example = "1½ cups <mark>chickpeas</mark> …")]
+    pub snippet: String,
+    /// BM25 score; lower is a better match.
+    pub rank: f64,
+}
+
+/// A page of search results.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    pub query: String,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub results: Vec<SearchHit>,
+}
+
+/// Turn free text into an FTS5 query of quoted prefix terms, so user input
+/// can never be read as FTS5 syntax. Returns `None` when no words remain.
+pub fn match_expression(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Escape text for HTML, turning the snippet markers into `<mark>` tags.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            '\u{1}' => html.push_str("<mark>"),
+            '\u{2}' => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Search recipes, best matches first, returning one page of results.
+pub async fn search(db: &SqlitePool, params: &SearchParams) -> Result<SearchResults, RecipeError> {
+    let expression = match_expression(&params.q).ok_or(RecipeError::EmptySearch)?;
+    let page = params.page.unwrap_or(1).clamp(1, MAX_PAGE);
+    let per_page = params.per_page.unwrap_or(10).clamp(1, MAX_PER_PAGE);
+
+    let total: i64 = sqlx::query_scalar("SELECT count(*) FROM recipes_fts WHERE recipes_fts MATCH $1;")
+        .bind(&expression)
+        .fetch_one(db)
+        .await?;
+
+    let mut results: Vec<SearchHit> = sqlx::query_as(
+        r#"SELECT recipe_id AS id, title,
+            snippet(recipes_fts, -1, char(1), char(2), '…', 12) AS snippet,
+            bm25(recipes_fts, 0.0, 10.0, 5.0, 1.0) AS rank
+        FROM recipes_fts WHERE recipes_fts MATCH $1
+        ORDER BY rank LIMIT $2 OFFSET $3;"#,
+    )
+    .bind(&expression)
+    .bind(per_page)
+    .bind((page - 1) * per_page)
+    .fetch_all(db)
+    .await?;
+    for hit in &mut results {
+        hit.snippet = highlight(&hit.snippet);
+    }
+
+    Ok(SearchResults {
+        query: params.q.clone(),
+        page,
+        per_page,
+        total,
+        results,
+    })
+}
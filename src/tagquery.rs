@@ -0,0 +1,241 @@
+//! Boolean tag queries such as `vegan AND (dessert OR snack) AND NOT nuts`.
+//!
+//! Grammar, loosest binding first:
+//!
+//! ```text
+//! query   := and (("OR" | ",") and)*
+//! and     := unary ("AND" unary)*
+//! unary   := "NOT" unary | "(" query ")" | tag
+//! tag     := word | "quoted words"
+//! ```
+//!
+//! Keywords are case-insensitive; quote a tag to use a keyword or spaces in it.
+//! A plain comma separated list such as `vegan,dessert` still means "any of".
+
+use crate::*;
+
+use sqlx::{QueryBuilder, Sqlite};
+
+/// Deepest nesting of parentheses and `NOT` accepted.
+const MAX_DEPTH: usize = 32;
+/// Most tags one query may name; each becomes a subquery in the SQL.
+pub const MAX_TERMS: usize = 64;
+
+/// Parsed tag query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagQuery {
+    Tag(String),
+    Not(Box<TagQuery>),
+    And(Vec<TagQuery>),
+    Or(Vec<TagQuery>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Tag(tag) => write!(f, "tag \"{}\"", tag),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+/// Split a query into tokens, each paired with its character position.
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, TagQueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                let token = match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Or,
+                };
+                tokens.push((position, token));
+            }
+            '"' => {
+                chars.next();
+                let mut tag = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => tag.push(c),
+                        None => return Err(TagQueryError::UnclosedQuote(position)),
+                    }
+                }
+                tokens.push((position, Token::Tag(tag.trim().to_string())));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | ',' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Tag(word),
+                };
+                tokens.push((position, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser over a token list.
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<(usize, Token)>>,
+    depth: usize,
+}
+
+impl Parser {
+    fn unexpected(&mut self) -> TagQueryError {
+        match self.tokens.next() {
+            Some((position, token)) => TagQueryError::Unexpected {
+                found: token.to_string(),
+                position,
+            },
+            None => TagQueryError::UnexpectedEnd,
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        self.tokens.next_if(|(_, t)| t == token).is_some()
+    }
+
+    fn query(&mut self) -> Result<TagQuery, TagQueryError> {
+        let mut terms = vec![self.and()?];
+        while self.eat(&Token::Or) {
+            terms.push(self.and()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => TagQuery::Or(terms),
+        })
+    }
+
+    fn and(&mut self) -> Result<TagQuery, TagQueryError> {
+        let mut terms = vec![self.unary()?];
+        while self.eat(&Token::And) {
+            terms.push(self.unary()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => TagQuery::And(terms),
+        })
+    }
+
+    fn unary(&mut self) -> Result<TagQuery, TagQueryError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(TagQueryError::TooDeep);
+        }
+        let term = if self.eat(&Token::Not) {
+            TagQuery::Not(Box::new(self.unary()?))
+        } else if self.eat(&Token::Open) {
+            let inner = self.query()?;
+            if !self.eat(&Token::Close) {
+                return Err(self.unexpected());
+            }
+            inner
+        } else {
+            match self.tokens.next_if(|(_, t)| matches!(t, Token::Tag(_))) {
+                Some((position, Token::Tag(tag))) if tag.is_empty() => {
+                    return Err(TagQueryError::EmptyTag(position));
+                }
+                Some((_, Token::Tag(tag))) => TagQuery::Tag(tag),
+                _ => return Err(self.unexpected()),
+            }
+        };
+        self.depth -= 1;
+        Ok(term)
+    }
+}
+
+impl TagQuery {
+    /// Parse a boolean tag expression.
+    pub fn parse(query: &str) -> Result<Self, TagQueryError> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Err(TagQueryError::Empty);
+        }
+        let terms = tokens.iter().filter(|(_, token)| matches!(token, Token::Tag(_))).count();
+        if terms > MAX_TERMS {
+            return Err(TagQueryError::TooManyTerms(MAX_TERMS));
+        }
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+            depth: 0,
+        };
+        let query = parser.query()?;
+        match parser.tokens.peek() {
+            None => Ok(query),
+            Some(_) => Err(parser.unexpected()),
+        }
+    }
+
+    /// Query matching recipes that have any of the given tags.
+    pub fn any<I: IntoIterator<Item = String>>(tags: I) -> Self {
+        TagQuery::Or(tags.into_iter().map(TagQuery::Tag).collect())
+    }
+
+    /// Query matching recipes that have every one of the given tags.
+    pub fn all<I: IntoIterator<Item = String>>(tags: I) -> Self {
+        TagQuery::And(tags.into_iter().map(TagQuery::Tag).collect())
+    }
+
+    /// Append a SQL condition on `recipes.id` for this query, binding every tag.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Sqlite>) {
+        let mut join = |terms: &[TagQuery], op: &str, empty: &str| {
+            if terms.is_empty() {
+                builder.push(empty);
+                return;
+            }
+            builder.push("(");
+            for (i, term) in terms.iter().enumerate() {
+                if i > 0 {
+                    builder.push(op);
+                }
+                term.push_sql(builder);
+            }
+            builder.push(")");
+        };
+        match self {
+            TagQuery::Tag(tag) => {
+                builder
+                    .push("recipes.id IN (SELECT recipe_id FROM tags WHERE tag = ")
+                    .push_bind(tag.clone())
+                    .push(")");
+            }
+            TagQuery::Not(inner) => {
+                builder.push("NOT ");
+                inner.push_sql(builder);
+            }
+            TagQuery::And(terms) => join(terms, " AND ", "1"),
+            TagQuery::Or(terms) => join(terms, " OR ", "0"),
+        }
+    }
+}
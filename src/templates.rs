@@ -8,14 +8,64 @@ pub struct IndexTemplate {
     recipe: Recipe,
     stylesheet: &'static str,
     tags: String,
+    scale: f64,
+    units: Option<UnitSystem>,
+    /// schema.org Recipe for the page's `application/ld+json` block.
+    json_ld: String,
 }
 
 impl IndexTemplate {
-    pub fn new(recipe: Recipe, tags: String) -> Self {
+    pub fn new(recipe: Recipe, tags: Vec<String>, scale: f64, units: Option<UnitSystem>) -> Self {
+        // `<` is escaped so no string in the document can close the script block
+        let json_ld = jsonld::document(&recipe, &tags).to_string().replace('<', "\\u003c");
         Self {
             recipe,
             stylesheet: "/recipe.css",
-            tags,
+            tags: tags.join(", "),
+            scale,
+            units,
+            json_ld,
         }
     }
+
+    /// Summary for link previews: the servings and the first few ingredients.
+    fn description(&self) -> String {
+        let lines: Vec<&str> = self
+            .recipe
+            .ingredients
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let mut ingredients = lines[..lines.len().min(4)].to_vec();
+        if lines.len() > 4 {
+            ingredients.push("...");
+        }
+        let ingredients = ingredients.join(", ");
+        match self.recipe.servings {
+            Some(servings) => format!("Serves {}. {}", servings, ingredients),
+            None => ingredients,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    results: search::SearchResults,
+    stylesheet: &'static str,
+}
+
+impl SearchTemplate {
+    pub fn new(results: search::SearchResults) -> Self {
+        Self {
+            results,
+            stylesheet: "/recipe.css",
+        }
+    }
+
+    /// Whether there are more results after this page.
+    fn has_next(&self) -> bool {
+        self.results.page.saturating_mul(self.results.per_page) < self.results.total
+    }
 }
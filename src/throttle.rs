@@ -0,0 +1,250 @@
+//! Brute-force protection for the endpoints that check passwords and invite codes.
+//!
+//! Failures are counted per client IP and per email. After `FREE_ATTEMPTS`
+//! failures the identity is locked out, for `BASE_LOCKOUT` doubling with each
+//! further failure up to `MAX_LOCKOUT`. A day without failures forgets them.
+//!
+//! Every attempt is counted as a failure before the credentials are checked,
+//! and given back once they turn out good, so guesses sent in parallel cannot
+//! all get past the lockout before the first of them fails.
+
+use crate::*;
+
+use std::net::IpAddr;
+
+/// Failures allowed before the first lockout.
+const FREE_ATTEMPTS: i64 = 5;
+/// Seconds of the first lockout.
+const BASE_LOCKOUT: i64 = 30;
+/// Seconds of the longest lockout.
+const MAX_LOCKOUT: i64 = 60 * 60;
+/// Seconds after the last failure when the count starts over.
+const RESET_AFTER: i64 = 24 * 60 * 60;
+
+/// What a failure is counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Kind {
+    Ip,
+    Email,
+}
+
+/// An identity that is locked out, as listed for admins.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Lockout {
+    pub kind: Kind,
+    #[schema(example = "203.0.113.7")]
+    pub identity: String,
+    pub failures: i64,
+    pub last_failure_at: String,
+    pub locked_until: String,
+    /// Seconds until the lockout ends.
+    pub retry_after: i64,
+}
+
+fn timestamp(secs: i64) -> String {
+    DateTime::from_timestamp(secs, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// Lockout after `failures` failures in a row, if any.
+fn lockout_secs(failures: i64) -> Option<i64> {
+    let extra = failures - FREE_ATTEMPTS;
+    if extra < 0 {
+        return None;
+    }
+    Some(BASE_LOCKOUT.saturating_mul(1 << extra.min(16)).min(MAX_LOCKOUT))
+}
+
+/// One attempt at an endpoint, counted against the client and the email it names.
+pub struct Attempt {
+    ip: String,
+    email: String,
+}
+
+impl Attempt {
+    pub fn new(ip: IpAddr, email: &str) -> Self {
+        Self {
+            ip: ip.to_string(),
+            email: email.trim().to_lowercase(),
+        }
+    }
+
+    fn identities(&self) -> [(Kind, &str); 2] {
+        [(Kind::Ip, &self.ip), (Kind::Email, &self.email)]
+    }
+
+    /// Refuse the attempt while the client or the email is locked out, and
+    /// otherwise count it as a failure until [`Attempt::succeeded`] or
+    /// [`Attempt::release`] gives it back.
+    ///
+    /// Database errors let the attempt through; it needs the database anyway.
+    pub async fn reserve(&self, db: &SqlitePool) -> Result<(), authjwt::AuthError> {
+        match reserve(db, &self.identities()).await {
+            Ok(0) => Ok(()),
+            Ok(wait) => Err(authjwt::AuthError::TooManyAttempts(wait)),
+            Err(e) => {
+                log::error!("throttle: reserving {} {}: {}", self.ip, self.email, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Give back a reserved attempt that failed for a reason other than bad
+    /// credentials, such as an invalid request.
+    pub async fn release(&self, db: &SqlitePool) {
+        for (kind, identity) in self.identities() {
+            if let Err(e) = release(db, kind, identity).await {
+                log::error!("throttle: releasing {:?} {}: {}", kind, identity, e);
+            }
+        }
+    }
+
+    /// Give back a reserved attempt whose credentials were good, and forget the
+    /// email's failures. The client's earlier failures stay, so one good account
+    /// does not buy more guesses at others.
+    pub async fn succeeded(&self, db: &SqlitePool) {
+        if let Err(e) = release(db, Kind::Ip, &self.ip).await {
+            log::error!("throttle: releasing {}: {}", self.ip, e);
+        }
+        match clear(db, Kind::Email, &self.email).await {
+            Ok(()) | Err(sqlx::Error::RowNotFound) => (),
+            Err(e) => log::error!("throttle: clearing {}: {}", self.email, e),
+        }
+    }
+}
+
+/// Count an attempt against every identity unless one is locked out, in which
+/// case nothing is counted and the seconds until the lockout ends are returned.
+async fn reserve(db: &SqlitePool, identities: &[(Kind, &str)]) -> Result<i64, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let forgotten = now - RESET_AFTER;
+    let mut tx = db.begin().await?;
+    // Writing first takes the database's write lock, so attempts are counted one at a time
+    sqlx::query!(
+        "DELETE FROM auth_throttle WHERE last_failure_at < $1;",
+        forgotten,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut counts = Vec::new();
+    let mut wait = 0;
+    for &(kind, identity) in identities {
+        let row = sqlx::query!(
+            "SELECT failures, locked_until FROM auth_throttle WHERE kind = $1 AND identity = $2;",
+            kind,
+            identity,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let (failures, locked_until) = row.map_or((0, None), |row| (row.failures, row.locked_until));
+        wait = wait.max(locked_until.unwrap_or(0) - now);
+        counts.push((kind, identity, failures + 1));
+    }
+    if wait > 0 {
+        tx.rollback().await?;
+        return Ok(wait);
+    }
+
+    for (kind, identity, failures) in counts {
+        let locked_until = lockout_secs(failures).map(|secs| now + secs);
+        if locked_until.is_some() {
+            log::warn!("throttle: locking out {:?} {} after {} failures", kind, identity, failures);
+        }
+        sqlx::query!(
+            r#"INSERT INTO auth_throttle (kind, identity, failures, last_failure_at, locked_until)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (kind, identity) DO UPDATE
+                SET failures = excluded.failures, last_failure_at = excluded.last_failure_at,
+                    locked_until = excluded.locked_until;"#,
+            kind,
+            identity,
+            failures,
+            now,
+            locked_until,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(0)
+}
+
+/// Take back one reserved failure, lifting the lockout it may have caused.
+async fn release(db: &SqlitePool, kind: Kind, identity: &str) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let failures = sqlx::query_scalar!(
+        r#"UPDATE auth_throttle SET failures = failures - 1
+        WHERE kind = $1 AND identity = $2
+        RETURNING failures;"#,
+        kind,
+        identity,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    match failures {
+        Some(failures) if failures <= 0 => {
+            sqlx::query!(
+                "DELETE FROM auth_throttle WHERE kind = $1 AND identity = $2;",
+                kind,
+                identity,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        Some(failures) if lockout_secs(failures).is_none() => {
+            sqlx::query!(
+                "UPDATE auth_throttle SET locked_until = NULL WHERE kind = $1 AND identity = $2;",
+                kind,
+                identity,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        _ => (),
+    }
+    tx.commit().await
+}
+
+/// Forget an identity's failures. Returns `RowNotFound` when it had none.
+pub async fn clear(db: &SqlitePool, kind: Kind, identity: &str) -> Result<(), sqlx::Error> {
+    let cleared = sqlx::query!(
+        "DELETE FROM auth_throttle WHERE kind = $1 AND identity = $2;",
+        kind,
+        identity,
+    )
+    .execute(db)
+    .await?;
+    if cleared.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Identities locked out right now, longest lockout first.
+pub async fn locked(db: &SqlitePool) -> Result<Vec<Lockout>, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let rows = sqlx::query!(
+        r#"SELECT kind AS "kind: Kind", identity, failures, last_failure_at,
+            locked_until AS "locked_until!"
+        FROM auth_throttle WHERE locked_until > $1 ORDER BY locked_until DESC;"#,
+        now,
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| Lockout {
+            kind: row.kind,
+            identity: row.identity,
+            failures: row.failures,
+            last_failure_at: timestamp(row.last_failure_at),
+            locked_until: timestamp(row.locked_until),
+            retry_after: row.locked_until - now,
+        })
+        .collect())
+}
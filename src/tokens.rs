@@ -0,0 +1,150 @@
+use crate::*;
+
+use password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// How long a refresh token stays usable.
+const REFRESH_TTL: TimeDelta = TimeDelta::days(30);
+
+/// Lowercase hex of some bytes.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Random hex string of `len` bytes from the OS generator.
+pub fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// SHA-256 of a token, which is all the database keeps.
+pub fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Store a new refresh token for a user and return it.
+///
+/// Tokens rotated from an earlier one pass its `family`; a fresh login starts a new one.
+pub async fn issue(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: i64,
+    family: Option<&str>,
+) -> Result<String, sqlx::Error> {
+    let token = random_hex(32);
+    let token_hash = hash(&token);
+    let family = family.map(str::to_string).unwrap_or_else(|| random_hex(16));
+    let expires_at = timestamp(Utc::now() + REFRESH_TTL);
+    sqlx::query!(
+        r#"INSERT INTO refresh_tokens (token_hash, family, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), $4);"#,
+        token_hash,
+        family,
+        user_id,
+        expires_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(token)
+}
+
+/// Trade a refresh token for a new one, returning the user id and the new token.
+///
+/// Each token works once. Presenting one that was already used means it leaked,
+/// so its whole family is revoked and `None` returned.
+pub async fn rotate(db: &SqlitePool, token: &str) -> Result<Option<(i64, String)>, sqlx::Error> {
+    let token_hash = hash(token);
+    let mut tx = db.begin().await?;
+    let row = sqlx::query!(
+        r#"SELECT family, user_id, expires_at, revoked_at FROM refresh_tokens
+        WHERE token_hash = $1;"#,
+        token_hash,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    if row.revoked_at.is_some() {
+        log::warn!("refresh token reused, revoking family {}", row.family);
+        revoke_family(&mut tx, &row.family).await?;
+        tx.commit().await?;
+        return Ok(None);
+    }
+    if row.expires_at < timestamp(Utc::now()) {
+        return Ok(None);
+    }
+
+    let used = sqlx::query!(
+        r#"UPDATE refresh_tokens SET revoked_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE token_hash = $1 AND revoked_at IS NULL;"#,
+        token_hash,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if used.rows_affected() == 0 {
+        return Ok(None);
+    }
+    let next = issue(&mut tx, row.user_id, Some(&row.family)).await?;
+    tx.commit().await?;
+    Ok(Some((row.user_id, next)))
+}
+
+async fn revoke_family(conn: &mut sqlx::SqliteConnection, family: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE refresh_tokens SET revoked_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+        WHERE family = $1 AND revoked_at IS NULL;"#,
+        family,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Revoke a refresh token and every token rotated from the same login, if it
+/// belongs to `user_id`.
+pub async fn revoke(db: &SqlitePool, token: &str, user_id: i64) -> Result<(), sqlx::Error> {
+    let token_hash = hash(token);
+    let mut conn = db.acquire().await?;
+    let family = sqlx::query_scalar!(
+        "SELECT family FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2;",
+        token_hash,
+        user_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(family) = family {
+        revoke_family(&mut conn, &family).await?;
+    }
+    Ok(())
+}
+
+/// Deny an access token until it expires, dropping entries that have expired.
+pub async fn deny(db: &SqlitePool, jti: &str, expires_at: i64) -> Result<(), sqlx::Error> {
+    let now = Utc::now().timestamp();
+    sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at < $1;", now)
+        .execute(db)
+        .await?;
+    sqlx::query!(
+        "INSERT OR IGNORE INTO revoked_tokens (jti, expires_at) VALUES ($1, $2);",
+        jti,
+        expires_at,
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Whether an access token has been revoked.
+pub async fn is_denied(db: &SqlitePool, jti: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1) AS "denied!: bool";"#,
+        jti,
+    )
+    .fetch_one(db)
+    .await
+}
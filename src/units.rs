@@ -0,0 +1,236 @@
+use crate::*;
+
+/// Measurement system a recipe can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,
+    Us,
+}
+
+impl std::fmt::Display for UnitSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitSystem::Metric => write!(f, "metric"),
+            UnitSystem::Us => write!(f, "us"),
+        }
+    }
+}
+
+/// Query parameter selecting the measurement system for a recipe.
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+pub struct UnitsParams {
+    /// Render quantities and oven temperatures in `metric` or `us` units.
+    pub units: Option<UnitSystem>,
+}
+
+/// Volume units and their size in milliliters.
+const VOLUMES: &[(&str, f64)] = &[
+    ("tsp", 4.928_92),
+    ("tbsp", 14.786_8),
+    ("fl oz", 29.573_5),
+    ("cup", 236.588),
+    ("pint", 473.176),
+    ("quart", 946.353),
+    ("gallon", 3785.41),
+    ("ml", 1.0),
+    ("l", 1000.0),
+];
+
+/// Weight units and their size in grams.
+const WEIGHTS: &[(&str, f64)] = &[
+    ("oz", 28.349_5),
+    ("lb", 453.592),
+    ("g", 1.0),
+    ("kg", 1000.0),
+];
+
+/// Grams per US cup of common baking staples, most specific names first.
+const DENSITIES: &[(&str, f64)] = &[
+    ("all-purpose flour", 125.0),
+    ("bread flour", 127.0),
+    ("whole wheat flour", 120.0),
+    ("cake flour", 114.0),
+    ("almond flour", 96.0),
+    ("flour", 125.0),
+    ("powdered sugar", 120.0),
+    ("confectioners sugar", 120.0),
+    ("brown sugar", 213.0),
+    ("granulated sugar", 200.0),
+    ("sugar", 200.0),
+    ("cocoa powder", 85.0),
+    ("butter", 227.0),
+    ("rolled oats", 90.0),
+    ("oats", 90.0),
+    ("quinoa", 170.0),
+    ("rice", 185.0),
+    ("honey", 340.0),
+    ("maple syrup", 322.0),
+    ("chocolate chips", 170.0),
+];
+
+fn volume_ml(unit: &str) -> Option<f64> {
+    VOLUMES.iter().find(|(u, _)| *u == unit).map(|(_, ml)| *ml)
+}
+
+fn weight_g(unit: &str) -> Option<f64> {
+    WEIGHTS.iter().find(|(u, _)| *u == unit).map(|(_, g)| *g)
+}
+
+/// Grams per milliliter for an ingredient item, if it is a known staple.
+fn density(item: &str) -> Option<f64> {
+    let item = item.to_lowercase();
+    DENSITIES
+        .iter()
+        .find(|(name, _)| item.contains(name))
+        .map(|(_, per_cup)| per_cup / 236.588)
+}
+
+/// Pick a unit from `choices` (smallest first) so the quantity reads naturally.
+fn best_unit(base: f64, choices: &[(&'static str, f64, f64)]) -> (&'static str, f64) {
+    choices
+        .iter()
+        .rev()
+        .find(|(_, size, min)| base >= size * min)
+        .or(choices.first())
+        .map(|(unit, size, _)| (*unit, base / size))
+        .unwrap_or(("", base))
+}
+
+/// Express an amount in base units (ml or g) in the target system.
+fn express(base: f64, is_weight: bool, system: UnitSystem) -> (&'static str, f64) {
+    let choices: &[(&str, f64, f64)] = match (system, is_weight) {
+        (UnitSystem::Metric, false) => &[("ml", 1.0, 0.0), ("l", 1000.0, 1.0)],
+        (UnitSystem::Metric, true) => &[("g", 1.0, 0.0), ("kg", 1000.0, 1.0)],
+        (UnitSystem::Us, false) => &[
+            ("tsp", 4.928_92, 0.0),
+            ("tbsp", 14.786_8, 1.0),
+            ("cup", 236.588, 0.25),
+        ],
+        (UnitSystem::Us, true) => &[("oz", 28.349_5, 0.0), ("lb", 453.592, 1.0)],
+    };
+    best_unit(base, choices)
+}
+
+/// Convert one ingredient's quantity and unit into the target system.
+pub fn convert_ingredient(ingredient: &Ingredient, system: UnitSystem) -> Ingredient {
+    let (Some(quantity), Some(unit)) = (ingredient.quantity, ingredient.unit.as_deref()) else {
+        return ingredient.clone();
+    };
+    // Nothing to convert, and the ratio for `quantity_max` would divide by zero
+    if quantity == 0.0 {
+        return ingredient.clone();
+    }
+    let metric = matches!(unit, "ml" | "l" | "g" | "kg");
+    if metric == (system == UnitSystem::Metric) {
+        return ingredient.clone();
+    }
+
+    // Base amount in ml or g, and whether it is a weight.
+    let to_base = if let Some(g) = weight_g(unit) {
+        Some((g, true))
+    } else if let Some(ml) = volume_ml(unit) {
+        match (system, density(&ingredient.item)) {
+            (UnitSystem::Metric, Some(g_per_ml)) => Some((ml * g_per_ml, true)),
+            _ => Some((ml, false)),
+        }
+    } else {
+        None
+    };
+    let Some((size, is_weight)) = to_base else {
+        return ingredient.clone();
+    };
+
+    let (unit, value) = express(quantity * size, is_weight, system);
+    let ratio = value / quantity;
+    let mut converted = ingredient.clone();
+    converted.quantity = Some(value);
+    converted.quantity_max = ingredient.quantity_max.map(|q| q * ratio);
+    converted.unit = Some(unit.to_string());
+    converted.raw = converted.render();
+    converted
+}
+
+/// Convert every line of a newline separated ingredient list.
+pub fn convert_lines(ingredients: &str, system: UnitSystem) -> String {
+    convert_all(&ingredient::parse_lines(ingredients), system)
+        .into_iter()
+        .map(|i| i.raw)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Convert each of a recipe's parsed ingredients into the target system.
+pub fn convert_all(ingredients: &[Ingredient], system: UnitSystem) -> Vec<Ingredient> {
+    ingredients.iter().map(|i| convert_ingredient(i, system)).collect()
+}
+
+/// Scale and byte length of a temperature marker such as "°F", " degrees C" or
+/// " Celsius".
+///
+/// A bare "C" or "F" only counts after a degree sign or "degrees", so neither
+/// "2C water" nor "2 c. flour" is read as a temperature. A bare "degrees"
+/// reports the scale as `'?'`.
+fn temperature_marker(s: &str) -> Option<(char, usize)> {
+    let spaced = s.trim_start_matches(' ');
+    let prefix = ["°", "º", "degrees", "degree"]
+        .into_iter()
+        .find(|p| spaced.starts_with(p));
+    let rest = match prefix {
+        Some(p) => spaced[p.len()..].trim_start_matches(' '),
+        None => spaced,
+    };
+    let consumed = |after: &str| s.len() - after.len();
+    let lower = rest.to_ascii_lowercase();
+    for (scale, name, letter) in [('F', "fahrenheit", "f"), ('C', "celsius", "c")] {
+        let names = if prefix.is_some() { &[name, letter][..] } else { &[name][..] };
+        if let Some(name) = names.iter().find(|n| lower.starts_with(*n)) {
+            let after = &rest[name.len()..];
+            if !after.starts_with(char::is_alphanumeric) {
+                return Some((scale, consumed(after)));
+            }
+        }
+    }
+    prefix.map(|p| ('?', consumed(&spaced[p.len()..])))
+}
+
+/// Rewrite oven temperatures in instruction text into the target system.
+pub fn convert_temperatures(text: &str, system: UnitSystem) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut prev: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let starts_number = c.is_ascii_digit() && !prev.is_some_and(|p| p.is_alphanumeric());
+        if starts_number {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let degrees: f64 = rest[..end].parse().unwrap_or_default();
+            if let Some((scale, len)) = temperature_marker(&rest[end..]) {
+                // No home oven goes above 260°C, so bare degrees past that are Fahrenheit.
+                let scale = match scale {
+                    '?' if degrees > 260.0 => 'F',
+                    scale => scale,
+                };
+                let converted = match (scale, system) {
+                    ('F', UnitSystem::Metric) => Some(((degrees - 32.0) * 5.0 / 9.0, 'C')),
+                    ('C', UnitSystem::Us) => Some((degrees * 9.0 / 5.0 + 32.0, 'F')),
+                    _ => None,
+                };
+                if let Some((value, scale)) = converted {
+                    let value = (value / 5.0).round() * 5.0;
+                    out.push_str(&format!("{}°{}", value, scale));
+                    rest = &rest[end + len..];
+                    prev = Some(scale);
+                    continue;
+                }
+            }
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            prev = Some('0');
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+        prev = Some(c);
+    }
+    out
+}
@@ -0,0 +1,349 @@
+use crate::*;
+
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+};
+
+/// Shortest password accepted at signup.
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// A registered user, as returned by the API.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct User {
+    pub id: i64,
+    #[schema(example = "firstlast@example.com")]
+    pub email: String,
+    #[schema(example = "First Last")]
+    pub display_name: String,
+    #[schema(example = "2025-06-01T12:00:00Z")]
+    pub created_at: String,
+    pub role: Role,
+    /// Invite the account was registered with.
+    pub invite_id: Option<i64>,
+}
+
+/// New role for a user.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RoleChange {
+    pub role: Role,
+}
+
+/// Signup request for a new account with a password.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Signup {
+    #[schema(example = "firstlast@example.com")]
+    pub email: String,
+    #[schema(example = "First Last")]
+    pub display_name: String,
+    #[schema(example = "correct horse battery staple")]
+    pub password: String,
+    /// Invite code from an admin.
+    #[schema(example = "3f9a1c0b7e4d2a6c58b1f0e2")]
+    pub access_code: String,
+}
+
+/// Email and password to exchange for a token.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Login {
+    #[schema(example = "firstlast@example.com")]
+    pub email: String,
+    #[schema(example = "correct horse battery staple")]
+    pub password: String,
+}
+
+impl IntoResponse for UserError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            UserError::InvalidEmail
+            | UserError::MissingName
+            | UserError::WeakPassword(_)
+            | UserError::InvalidApiKey(_)
+            | UserError::InvalidInvite(_) => StatusCode::BAD_REQUEST,
+            UserError::InviteRejected => StatusCode::UNAUTHORIZED,
+            UserError::EmailTaken => StatusCode::CONFLICT,
+            UserError::NotFound => StatusCode::NOT_FOUND,
+            UserError::Hashing | UserError::Database(_) => {
+                log::error!("users: {}", self);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        let body = Json(serde_json::json!({
+            "status": status.as_u16(),
+            "error": self.to_string(),
+        }));
+        (status, body).into_response()
+    }
+}
+
+/// Emails are compared without surrounding space or case.
+pub fn normalize_email(email: &str) -> Result<String, UserError> {
+    let email = email.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') => Ok(email),
+        _ => Err(UserError::InvalidEmail),
+    }
+}
+
+/// Hash a password with Argon2id off the async runtime.
+async fn hash_password(password: String) -> Result<String, UserError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|_| UserError::Hashing)?
+    .map_err(|_| UserError::Hashing)
+}
+
+/// Check a password against a stored hash off the async runtime.
+async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash)
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Create an account with a password, or set one on a passwordless account
+/// with an invite reserved for its email.
+pub async fn create(db: &SqlitePool, signup: Signup) -> Result<User, UserError> {
+    let email = normalize_email(&signup.email)?;
+    let display_name = signup.display_name.trim().to_string();
+    if display_name.is_empty() {
+        return Err(UserError::MissingName);
+    }
+    if signup.password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(UserError::WeakPassword(MIN_PASSWORD_LEN));
+    }
+    let password_hash = hash_password(signup.password).await?;
+
+    let mut tx = db.begin().await?;
+    let invite = invites::find(&mut tx, &signup.access_code)
+        .await?
+        .filter(|invite| invite.email.as_ref().is_none_or(|reserved| *reserved == email))
+        .ok_or(UserError::InviteRejected)?;
+    if !invites::consume(&mut tx, invite.id).await? {
+        return Err(UserError::InviteRejected);
+    }
+    let inserted = sqlx::query_as!(
+        User,
+        r#"INSERT INTO users (email, display_name, password_hash, invite_id, created_at)
+        VALUES ($1, $2, $3, $4, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id;"#,
+        email,
+        display_name,
+        password_hash,
+        invite.id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let user = match inserted {
+        Some(user) => user,
+        // A shared code could be held by anyone, so only one reserved for this
+        // email may claim its passwordless account; name and invite are kept
+        None if invite.email.is_some() => sqlx::query_as!(
+            User,
+            r#"UPDATE users SET password_hash = $2
+            WHERE email = $1 AND password_hash IS NULL
+            RETURNING id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id;"#,
+            email,
+            password_hash,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::EmailTaken)?,
+        None => return Err(UserError::EmailTaken),
+    };
+    tx.commit().await?;
+    Ok(user)
+}
+
+/// Create a passwordless account with an invite code, using up one of its uses.
+///
+/// An email that is already registered is refused whatever the invite, so a
+/// code shared among several people cannot be used to act as one of them.
+pub async fn register(
+    db: &SqlitePool,
+    full_name: &str,
+    email: &str,
+    code: &str,
+) -> Result<User, UserError> {
+    let email = normalize_email(email)?;
+    let display_name = full_name.trim();
+    if display_name.is_empty() {
+        return Err(UserError::MissingName);
+    }
+
+    let mut tx = db.begin().await?;
+    let invite = invites::find(&mut tx, code)
+        .await?
+        .filter(|invite| invite.email.as_ref().is_none_or(|reserved| *reserved == email))
+        .ok_or(UserError::InviteRejected)?;
+    if !invites::consume(&mut tx, invite.id).await? {
+        return Err(UserError::InviteRejected);
+    }
+    let user = sqlx::query_as!(
+        User,
+        r#"INSERT INTO users (email, display_name, invite_id, created_at)
+        VALUES ($1, $2, $3, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id;"#,
+        email,
+        display_name,
+        invite.id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(UserError::EmailTaken)?;
+    tx.commit().await?;
+    Ok(user)
+}
+
+/// Look up a password account by email and check its password.
+pub async fn authenticate(db: &SqlitePool, login: Login) -> Result<Option<User>, UserError> {
+    let Ok(email) = normalize_email(&login.email) else {
+        return Ok(None);
+    };
+    let row = sqlx::query!(
+        r#"SELECT id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id,
+            password_hash AS "password_hash!"
+        FROM users WHERE email = $1 AND password_hash IS NOT NULL;"#,
+        email,
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    if !verify_password(login.password, row.password_hash).await {
+        return Ok(None);
+    }
+
+    Ok(Some(User {
+        id: row.id,
+        email: row.email,
+        display_name: row.display_name,
+        created_at: row.created_at,
+        role: row.role,
+        invite_id: row.invite_id,
+    }))
+}
+
+/// Fetch a user by id.
+pub async fn get(db: &SqlitePool, user_id: i64) -> Result<User, UserError> {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id
+        FROM users WHERE id = $1;"#,
+        user_id,
+    )
+    .fetch_optional(db)
+    .await?;
+    user.ok_or(UserError::NotFound)
+}
+
+/// Every user, oldest account first.
+pub async fn list(db: &SqlitePool) -> Result<Vec<User>, UserError> {
+    let users = sqlx::query_as!(
+        User,
+        r#"SELECT id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id
+        FROM users ORDER BY id;"#,
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(users)
+}
+
+/// Change a user's role. It applies to tokens issued from then on.
+pub async fn set_role(db: &SqlitePool, user_id: i64, role: Role) -> Result<User, UserError> {
+    let user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET role = $2 WHERE id = $1
+        RETURNING id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id;"#,
+        user_id,
+        role,
+    )
+    .fetch_optional(db)
+    .await?;
+    user.ok_or(UserError::NotFound)
+}
+
+/// Make the user with this email an admin, for bootstrapping from the command line.
+pub async fn grant_admin(db: &SqlitePool, email: &str) -> Result<User, UserError> {
+    let email = normalize_email(email)?;
+    let user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET role = 'admin' WHERE email = $1
+        RETURNING id AS "id!", email, display_name, created_at, role AS "role: Role", invite_id;"#,
+        email,
+    )
+    .fetch_optional(db)
+    .await?;
+    user.ok_or(UserError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use invites::NewInvite;
+
+    #[tokio::test]
+    async fn shared_invite_cannot_claim_an_existing_account() {
+        let db = test_db().await;
+        let shared = NewInvite {
+            max_uses: Some(3),
+            ..Default::default()
+        };
+        let invite = invites::create(&db, None, shared).await.expect("invite");
+        register(&db, "Ann", "ann@example.com", &invite.code).await.expect("register");
+
+        let again = register(&db, "Mallory", "ann@example.com", &invite.code).await;
+        assert!(matches!(again, Err(UserError::EmailTaken)));
+        let signup = Signup {
+            email: "ann@example.com".to_string(),
+            display_name: "Mallory".to_string(),
+            password: "takeover123".to_string(),
+            access_code: invite.code.clone(),
+        };
+        assert!(matches!(create(&db, signup).await, Err(UserError::EmailTaken)));
+
+        let login = Login {
+            email: "ann@example.com".to_string(),
+            password: "takeover123".to_string(),
+        };
+        assert!(authenticate(&db, login).await.expect("authenticate").is_none());
+    }
+
+    #[tokio::test]
+    async fn invite_reserved_for_the_email_sets_its_password() {
+        let db = test_db().await;
+        let invite = invites::create(&db, None, NewInvite::default()).await.expect("invite");
+        let ann = register(&db, "Ann", "ann@example.com", &invite.code).await.expect("register");
+
+        let reserved = |email: &str| NewInvite {
+            email: Some(email.to_string()),
+            ..Default::default()
+        };
+        let signup = |code: &str| Signup {
+            email: "ann@example.com".to_string(),
+            display_name: "Someone Else".to_string(),
+            password: "correct horse".to_string(),
+            access_code: code.to_string(),
+        };
+        let other = invites::create(&db, None, reserved("bob@example.com")).await.expect("invite");
+        assert!(matches!(create(&db, signup(&other.code)).await, Err(UserError::InviteRejected)));
+
+        let claim = invites::create(&db, None, reserved("ann@example.com")).await.expect("invite");
+        let claimed = create(&db, signup(&claim.code)).await.expect("claim");
+        assert_eq!(claimed.id, ann.id);
+        assert_eq!(claimed.display_name, "Ann");
+        assert_eq!(claimed.invite_id, Some(invite.info.id));
+        assert!(matches!(create(&db, signup(&claim.code)).await, Err(UserError::InviteRejected)));
+    }
+}
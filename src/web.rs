@@ -4,21 +4,34 @@ use crate::*;
 pub struct GetRecipeParams {
     id: Option<String>,
     tags: Option<String>,
+    scale: Option<f64>,
+    servings: Option<i64>,
+    units: Option<UnitSystem>,
+    q: Option<String>,
+    page: Option<i64>,
 }
 
 /// Handles the `/` route and returns a recipe based on query parameters.
 ///
-/// This handler supports three query types:
+/// This handler supports four query types:
 /// 1. If an `id` is provided as a query parameter, it fetches the recipe by that ID
 ///    and renders the recipe page with associated tags.
-/// 2. If `tags` are provided as a comma-separated query parameter, it randomly selects
-///    a recipe that matches one or more of those tags and redirects to the recipe's page.
-/// 3. If neither `id` nor `tags` are provided, it randomly selects any recipe and redirects to its page.
+/// 2. If `tags` are provided, either as a comma-separated list or a boolean expression
+///    using `AND`, `OR`, `NOT` and parentheses, it randomly selects a matching recipe and
+///    redirects to the recipe's page. A malformed expression is a 400 error.
+/// 3. If `q` is provided, it runs a full-text search and renders a page of ranked results.
+/// 4. If none of these are provided, it randomly selects any recipe and redirects to its page.
 ///
 /// # Query Examples
 /// - `/?id=example-id`: Fetch a specific recipe by ID.
 /// - `/?tags=beef`: Fetch a random recipe with one of the given tags.
+/// - `/?tags=vegetarian AND NOT salad`: Fetch a random recipe matching the expression.
+/// - `/?q=chickpeas&page=2`: Search titles, ingredients and instructions.
 /// - `/`: Fallback to a random recipe.
+///
+/// A recipe fetched by `id` can also be resized with `scale` (a multiplier) or
+/// `servings` (a target serving count), e.g. `/?id=example-id&scale=2`, and
+/// rendered in other units with `units=metric` or `units=us`.
 pub async fn get_recipe(
     State(app_state): State<Arc<RwLock<AppState>>>,
     Query(params): Query<GetRecipeParams>,
@@ -28,13 +41,34 @@ pub async fn get_recipe(
 
     // User has passed the id in the params
     if let GetRecipeParams { id: Some(id), .. } = params {
+        let scale = ScaleParams {
+            scale: params.scale,
+            servings: params.servings,
+        };
         let recipe_result = recipe::get(&db, &id).await;
         let result = match recipe_result {
-            Ok((recipe, tags)) => {
-                let tag_string = tags.join(", ");
+            Ok((mut recipe, tags)) => {
+                let factor = match scale.factor(recipe.servings) {
+                    Ok(factor) => factor,
+                    Err(e) => {
+                        log::info!("recipe scale rejected: {}", e);
+                        return Err(http::StatusCode::BAD_REQUEST);
+                    }
+                };
+                if let Some(factor) = factor {
+                    recipe.scale(factor);
+                }
+                if let Some(system) = params.units {
+                    recipe.convert_units(system);
+                }
 
                 app_state.current_recipe = recipe.clone();
-                let recipe = IndexTemplate::new(recipe.clone(), tag_string);
+                let recipe = IndexTemplate::new(
+                    recipe.clone(),
+                    tags,
+                    factor.unwrap_or(1.0),
+                    params.units,
+                );
                 Ok(response::Html(recipe.to_string()).into_response())
             }
             Err(e) => {
@@ -45,14 +79,37 @@ pub async fn get_recipe(
         return result;
     }
 
+    // User passed a search in the params
+    if let GetRecipeParams { q: Some(q), .. } = params {
+        let search = search::SearchParams {
+            q,
+            page: params.page,
+            per_page: None,
+        };
+        return match search::search(&db, &search).await {
+            Ok(results) => Ok(response::Html(SearchTemplate::new(results).to_string()).into_response()),
+            Err(RecipeError::EmptySearch) => Ok(response::Redirect::to("/").into_response()),
+            Err(e) => {
+                log::error!("recipe search failed: {}", e);
+                Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+    }
+
     // User passed tags in the params
     if let GetRecipeParams {
         tags: Some(tags), ..
     } = params
     {
         log::info!("recipe tags: {}", tags);
-        let tags_vec: Vec<String> = tags.split(",").map(str::to_string).collect();
-        let recipe_result = get_random_from_tags(&db, tags_vec).await;
+        let query = match TagQuery::parse(&tags) {
+            Ok(query) => query,
+            Err(e) => {
+                let message = format!("invalid tag query: {}", e);
+                return Ok((http::StatusCode::BAD_REQUEST, message).into_response());
+            }
+        };
+        let recipe_result = get_random_from_tags(&db, &query).await;
 
         match recipe_result {
             Ok((recipe, _)) => {
@@ -65,7 +122,7 @@ pub async fn get_recipe(
             }
             Err(e) => {
                 log::error!("tagged recipe selection database error: {}", e);
-                panic!("tagged recipe selection database error");
+                return Err(http::StatusCode::INTERNAL_SERVER_ERROR);
             }
         }
     }